@@ -0,0 +1,357 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use color_eyre::eyre::Result;
+use neo4rs::{BoltMap, BoltType};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::instrument;
+
+use crate::{
+    concurrency::{Preconditions, Version},
+    dry_run::{DryRun, Written},
+    ids::MovieKey,
+    limits::{self, Limited},
+    queries, rows,
+    tx::Tx,
+    AppError, HttpError, Service,
+};
+
+/// Generic CRUD endpoints over nodes of any label that exists in the graph.
+pub fn routes() -> Router<Service> {
+    Router::new()
+        .route("/admin/nodes/:label", get(list).post(create))
+        .route(
+            "/admin/nodes/:label/:id",
+            get(fetch).put(update).delete(delete),
+        )
+}
+
+async fn list(
+    Path(label): Path<String>,
+    Query(page): Query<Page>,
+    Limited(limit, _): Limited<limits::Admin>,
+    State(service): State<Service>,
+) -> Result<Json<NodePage>, AppError> {
+    Ok(Json(service.admin_list(label, page, limit).await?))
+}
+
+async fn fetch(
+    Path((label, id)): Path<(String, String)>,
+    State(service): State<Service>,
+) -> Result<Json<AdminNode>, AppError> {
+    Ok(Json(service.admin_get(label, id).await?))
+}
+
+async fn create(
+    Path(label): Path<String>,
+    State(service): State<Service>,
+    dry_run: DryRun,
+    Json(properties): Json<Map<String, Value>>,
+) -> Result<Response, AppError> {
+    let written = service.admin_create(label, properties, dry_run).await?;
+    Ok(written.respond(Json))
+}
+
+async fn update(
+    Path((label, id)): Path<(String, String)>,
+    State(service): State<Service>,
+    dry_run: DryRun,
+    headers: HeaderMap,
+    Json(properties): Json<Map<String, Value>>,
+) -> Result<Response, AppError> {
+    let preconditions = Preconditions::from_headers(&headers)?;
+    let written = service
+        .admin_update(label, id, preconditions, properties, dry_run)
+        .await?;
+    Ok(written.respond(Json))
+}
+
+async fn delete(
+    Path((label, id)): Path<(String, String)>,
+    State(service): State<Service>,
+    dry_run: DryRun,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let preconditions = Preconditions::from_headers(&headers)?;
+    let written = service
+        .admin_delete(label, id, preconditions, dry_run)
+        .await?;
+    Ok(written.respond(Json))
+}
+
+impl Service {
+    #[instrument(skip(self))]
+    async fn admin_list(&self, label: String, page: Page, limit: i64) -> Result<NodePage> {
+        let skip = page.skip.unwrap_or(0);
+        if skip < 0 {
+            return Err(HttpError::bad_request("skip must not be negative").into());
+        }
+
+        let nodes = self
+            .execute_read(|tx| {
+                let label = label.clone();
+                Box::pin(async move {
+                    ensure_label(tx, &label).await?;
+                    let rows = tx
+                        .execute_labeled(
+                            queries::ADMIN_LIST_NODES.name,
+                            Some(&label),
+                            [("skip", skip.into()), ("limit", limit.into())],
+                        )
+                        .await?;
+                    rows.iter().map(rows::from_row::<AdminNode>).collect()
+                })
+            })
+            .await?;
+
+        Ok(NodePage { nodes, skip, limit })
+    }
+
+    #[instrument(skip(self))]
+    async fn admin_get(&self, label: String, id: String) -> Result<AdminNode> {
+        self.execute_read(|tx| {
+            let label = label.clone();
+            let id = id.clone();
+            Box::pin(async move {
+                ensure_label(tx, &label).await?;
+                let rows = tx
+                    .execute_labeled(
+                        queries::ADMIN_GET_NODE.name,
+                        Some(&label),
+                        [("id", id.clone().into())],
+                    )
+                    .await?;
+                single_node(&rows, &label, &id)
+            })
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn admin_create(
+        &self,
+        label: String,
+        properties: Map<String, Value>,
+        dry_run: DryRun,
+    ) -> Result<Written<AdminNode>> {
+        let properties = to_properties(properties)?;
+
+        self.execute_mutation(dry_run, |tx| {
+            let label = label.clone();
+            let properties = properties.clone();
+            Box::pin(async move {
+                ensure_label(tx, &label).await?;
+                let rows = tx
+                    .execute_labeled(
+                        queries::ADMIN_CREATE_NODE.name,
+                        Some(&label),
+                        [("properties", BoltType::Map(properties))],
+                    )
+                    .await?;
+                rows::from_row(&rows[0])
+            })
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn admin_update(
+        &self,
+        label: String,
+        id: String,
+        preconditions: Preconditions,
+        properties: Map<String, Value>,
+        dry_run: DryRun,
+    ) -> Result<Written<AdminNode>> {
+        let properties = to_properties(properties)?;
+
+        let node = self
+            .execute_mutation(dry_run, |tx| {
+                let label = label.clone();
+                let id = id.clone();
+                let properties = properties.clone();
+                let preconditions = preconditions.clone();
+                Box::pin(async move {
+                    ensure_label(tx, &label).await?;
+                    check_preconditions(tx, &label, &id, &preconditions).await?;
+                    let rows = tx
+                        .execute_labeled(
+                            queries::ADMIN_UPDATE_NODE.name,
+                            Some(&label),
+                            [
+                                ("id", id.clone().into()),
+                                ("properties", BoltType::Map(properties)),
+                            ],
+                        )
+                        .await?;
+                    single_node(&rows, &label, &id)
+                })
+            })
+            .await;
+
+        if let Ok(Written::Committed(node)) = &node {
+            self.forget_node(&node.properties);
+        }
+        node
+    }
+
+    #[instrument(skip(self))]
+    async fn admin_delete(
+        &self,
+        label: String,
+        id: String,
+        preconditions: Preconditions,
+        dry_run: DryRun,
+    ) -> Result<Written<Deleted>> {
+        let deleted = self
+            .execute_mutation(dry_run, |tx| {
+                let label = label.clone();
+                let id = id.clone();
+                let preconditions = preconditions.clone();
+                Box::pin(async move {
+                    ensure_label(tx, &label).await?;
+                    check_preconditions(tx, &label, &id, &preconditions).await?;
+                    let rows = tx
+                        .execute_labeled(
+                            queries::ADMIN_DELETE_NODE.name,
+                            Some(&label),
+                            [("id", id.clone().into())],
+                        )
+                        .await?;
+                    let deleted = rows::from_row::<Deleted>(&rows[0])?;
+                    if deleted.deleted == 0 {
+                        return Err(not_found(&label, &id).into());
+                    }
+                    Ok(deleted)
+                })
+            })
+            .await;
+
+        if let Ok(Written::Committed(Deleted {
+            properties: Some(properties),
+            ..
+        })) = &deleted
+        {
+            self.forget_node(properties);
+        }
+        deleted
+    }
+
+    /// Evicts the node from the movie caches, in case it is a movie.
+    fn forget_node(&self, properties: &Map<String, Value>) {
+        if let Some(Value::String(id)) = properties.get("id") {
+            self.movies.remove(&MovieKey::Id(id.clone()));
+        }
+
+        let previous_titles = match properties.get("previousTitles") {
+            Some(Value::Array(titles)) => titles.as_slice(),
+            _ => &[],
+        };
+        let titles = properties.get("title").into_iter().chain(previous_titles);
+        for title in titles.filter_map(Value::as_str) {
+            self.forget_movie(title);
+            self.ids.remove(&title.to_owned());
+        }
+    }
+}
+
+/// Only labels that already exist in the graph can be managed.
+async fn ensure_label(tx: &mut Tx, label: &str) -> Result<()> {
+    let rows = tx.execute(queries::LABELS.name, []).await?;
+    let Labels { labels } = rows::from_row(&rows[0])?;
+    if !labels.iter().any(|l| l == label) {
+        return Err(HttpError::not_found(format!("unknown label {label}")).into());
+    }
+    Ok(())
+}
+
+/// Locks the node and checks the conditional request headers against it.
+async fn check_preconditions(
+    tx: &mut Tx,
+    label: &str,
+    id: &str,
+    preconditions: &Preconditions,
+) -> Result<()> {
+    if preconditions.is_empty() {
+        return Ok(());
+    }
+
+    let rows = tx
+        .execute_labeled(
+            queries::ADMIN_LOCK_NODE.name,
+            Some(label),
+            [("id", id.into())],
+        )
+        .await?;
+    let row = rows.first().ok_or_else(|| not_found(label, id))?;
+    preconditions.evaluate(&rows::from_row::<Version>(row)?)
+}
+
+fn single_node(rows: &[neo4rs::Row], label: &str, id: &str) -> Result<AdminNode> {
+    let row = rows.first().ok_or_else(|| not_found(label, id))?;
+    rows::from_row(row)
+}
+
+fn not_found(label: &str, id: &str) -> HttpError {
+    HttpError::not_found(format!("no {label} node with id {id}"))
+}
+
+/// Converts a JSON object into node properties, which may only hold
+/// primitive values or lists of them.
+fn to_properties(properties: Map<String, Value>) -> Result<BoltMap> {
+    let mut map = BoltMap::with_capacity(properties.len());
+    for (key, value) in properties {
+        let valid = match &value {
+            Value::Object(_) => false,
+            Value::Array(items) => items
+                .iter()
+                .all(|item| !matches!(item, Value::Object(_) | Value::Array(_) | Value::Null)),
+            _ => true,
+        };
+        if !valid {
+            return Err(HttpError::bad_request(format!(
+                "property {key} must be a primitive value or a list of primitive values"
+            ))
+            .into());
+        }
+        map.put(key.into(), BoltType::try_from(value)?);
+    }
+    Ok(map)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Page {
+    skip: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NodePage {
+    nodes: Vec<AdminNode>,
+    skip: i64,
+    limit: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AdminNode {
+    /// The element id, which is only stable within one database.
+    id: String,
+    properties: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Labels {
+    labels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Deleted {
+    deleted: i64,
+    /// The properties the node had, to evict it from the caches.
+    #[serde(default, skip_serializing)]
+    properties: Option<Map<String, Value>>,
+}
@@ -0,0 +1,244 @@
+use std::time::Duration;
+
+use axum::{extract::State, routing::post, Json, Router};
+use color_eyre::eyre::Result;
+use neo4rs::{BoltMap, BoltType};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::{debug, instrument};
+
+use crate::{queries::Access, rows, tx, AppError, HttpError, Service};
+
+const MAX_QUESTION_LENGTH: usize = 500;
+const MAX_ROWS: usize = 100;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Clauses and keywords that may change data or reach outside the graph.
+const FORBIDDEN_KEYWORDS: &[&str] = &[
+    "CREATE",
+    "MERGE",
+    "DELETE",
+    "DETACH",
+    "SET",
+    "REMOVE",
+    "DROP",
+    "LOAD",
+    "FOREACH",
+    "CALL",
+    "USE",
+    "ALTER",
+    "GRANT",
+    "DENY",
+    "REVOKE",
+    "SHOW",
+    "TERMINATE",
+    "START",
+    "STOP",
+    "FINISH",
+];
+
+pub fn routes() -> Router<Service> {
+    Router::new().route("/ask", post(ask))
+}
+
+async fn ask(
+    State(service): State<Service>,
+    Json(question): Json<Question>,
+) -> Result<Json<Answer>, AppError> {
+    Ok(Json(service.ask(question).await?))
+}
+
+impl Service {
+    #[instrument(skip(self))]
+    async fn ask(&self, Question { question }: Question) -> Result<Answer> {
+        let Some(generator) = &self.generator else {
+            return Err(HttpError::service_unavailable(
+                "natural-language search is not configured",
+            )
+            .into());
+        };
+
+        let question = question.trim();
+        if question.is_empty() || question.chars().count() > MAX_QUESTION_LENGTH {
+            return Err(HttpError::bad_request(format!(
+                "the question must have between 1 and {MAX_QUESTION_LENGTH} characters"
+            ))
+            .into());
+        }
+
+        let generated = generator.generate(question).await?;
+        debug!(?generated);
+
+        check_read_only(&generated.cypher)?;
+        let mut params = BoltMap::with_capacity(generated.params.len());
+        for (key, value) in generated.params.clone() {
+            params.put(key.into(), BoltType::try_from(value)?);
+        }
+
+        // the row limit is enforced by the server, the generated query
+        // runs as a subquery so its own RETURN stays intact
+        let cypher = format!(
+            "CALL {{\n{}\n}}\nRETURN *\nLIMIT {}",
+            generated.cypher.trim().trim_end_matches(';'),
+            MAX_ROWS + 1
+        );
+        let query = neo4rs::Query::new(cypher).params(params.value);
+
+        // the transaction is rolled back even if the query was let through
+        let rows = self
+            .db
+            .with_graph(|graph| {
+                let query = query.clone();
+                async move {
+                    tx::run_rolled_back(&graph, Access::Read, |tx| {
+                        let query = query.clone();
+                        Box::pin(async move { tx.execute_unregistered(query, TIMEOUT).await })
+                    })
+                    .await
+                }
+            })
+            .await?;
+
+        let truncated = rows.len() > MAX_ROWS;
+        let rows = rows
+            .iter()
+            .take(MAX_ROWS)
+            .map(rows::from_row::<Map<String, Value>>)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Answer {
+            question: question.to_owned(),
+            cypher: generated.cypher,
+            params: generated.params,
+            rows,
+            truncated,
+        })
+    }
+}
+
+/// Rejects generated Cypher that is not a single read-only statement.
+fn check_read_only(cypher: &str) -> Result<()> {
+    let code = strip_literals_and_comments(cypher);
+
+    if code.trim().trim_end_matches(';').contains(';') {
+        return Err(HttpError::bad_request("the generated query has several statements").into());
+    }
+
+    let words = code
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map(|word| word.to_ascii_uppercase())
+        .collect::<Vec<_>>();
+
+    if let Some(word) = words
+        .iter()
+        .find(|word| FORBIDDEN_KEYWORDS.contains(&word.as_str()))
+    {
+        return Err(HttpError::bad_request(format!(
+            "the generated query is not read-only (uses {word})"
+        ))
+        .into());
+    }
+    if !words.iter().any(|word| word == "RETURN") {
+        return Err(HttpError::bad_request("the generated query does not return anything").into());
+    }
+
+    Ok(())
+}
+
+/// Blanks out string literals, quoted identifiers and comments, so that
+/// keywords inside them are not mistaken for clauses.
+fn strip_literals_and_comments(cypher: &str) -> String {
+    let mut out = String::with_capacity(cypher.len());
+    let mut chars = cypher.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                let mut escaped = false;
+                for next in chars.by_ref() {
+                    if escaped {
+                        escaped = false;
+                    } else if next == '\\' && c != '`' {
+                        escaped = true;
+                    } else if next == c {
+                        break;
+                    }
+                }
+                out.push(' ');
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+                out.push('\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+                out.push(' ');
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Question {
+    question: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Answer {
+    question: String,
+    cypher: String,
+    params: Map<String, Value>,
+    rows: Vec<Map<String, Value>>,
+    truncated: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_read_queries() {
+        check_read_only(
+            "MATCH (p:Person)-[:ACTED_IN]->(m:Movie {title: $title}) RETURN p.name AS name",
+        )
+        .unwrap();
+        check_read_only("MATCH (m:Movie) WHERE m.tagline CONTAINS 'create' RETURN m.title;")
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_writes_and_procedures() {
+        for cypher in [
+            "MATCH (m:Movie) DETACH DELETE m RETURN count(*)",
+            "MATCH (m:Movie) SET m.votes = 0 RETURN m",
+            "merge (p:Person {name: $name}) return p",
+            "CALL dbms.killQueries([]) YIELD queryId RETURN queryId",
+            "LOAD CSV FROM 'http://example.com' AS row RETURN row",
+            "MATCH (m) RETURN m; MATCH (m) DELETE m",
+        ] {
+            assert!(check_read_only(cypher).is_err(), "{cypher}");
+        }
+    }
+
+    #[test]
+    fn ignores_keywords_in_literals_and_comments() {
+        check_read_only(
+            "MATCH (m:`Set`) // delete everything\nWHERE m.title = \"Merge\" /* drop */ RETURN m",
+        )
+        .unwrap();
+    }
+}
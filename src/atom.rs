@@ -0,0 +1,108 @@
+use chrono::{DateTime, FixedOffset};
+
+use crate::xml::escape;
+
+/// An Atom feed (RFC 4287) with the few elements the application needs.
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub id: String,
+    pub title: String,
+    /// Also the author of every entry, which RFC 4287 requires.
+    pub author: String,
+    pub link: String,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: String,
+    pub title: String,
+    pub link: String,
+    pub published: DateTime<FixedOffset>,
+    pub updated: DateTime<FixedOffset>,
+    pub summary: Option<String>,
+}
+
+impl Feed {
+    /// The feed was last updated when its most recently updated entry was.
+    fn updated(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .map_or_else(
+                || "1970-01-01T00:00:00Z".to_owned(),
+                |updated| updated.to_rfc3339(),
+            )
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        xml.push_str(&element("id", &self.id));
+        xml.push_str(&element("title", &self.title));
+        xml.push_str(&element("updated", &self.updated()));
+        xml.push_str(&format!(
+            "<link rel=\"self\" href=\"{}\"/>\n",
+            escape(&self.link)
+        ));
+        xml.push_str(&format!(
+            "<author>\n{}</author>\n",
+            element("name", &self.author)
+        ));
+        for entry in &self.entries {
+            entry.write(&mut xml);
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+}
+
+impl Entry {
+    fn write(&self, xml: &mut String) {
+        xml.push_str("<entry>\n");
+        xml.push_str(&element("id", &self.id));
+        xml.push_str(&element("title", &self.title));
+        xml.push_str(&format!("<link href=\"{}\"/>\n", escape(&self.link)));
+        xml.push_str(&element("published", &self.published.to_rfc3339()));
+        xml.push_str(&element("updated", &self.updated.to_rfc3339()));
+        if let Some(summary) = &self.summary {
+            xml.push_str(&element("summary", summary));
+        }
+        xml.push_str("</entry>\n");
+    }
+}
+
+fn element(name: &str, text: &str) -> String {
+    format!("<{name}>{}</{name}>\n", escape(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_entries() {
+        let date = DateTime::parse_from_rfc3339("2024-01-01T10:00:00Z").unwrap();
+        let feed = Feed {
+            id: "http://localhost/feed.atom".to_owned(),
+            title: "New movies".to_owned(),
+            author: "Movies & Co".to_owned(),
+            link: "http://localhost/feed.atom".to_owned(),
+            entries: vec![Entry {
+                id: "http://localhost/movie/A".to_owned(),
+                title: "Tom & Jerry".to_owned(),
+                link: "http://localhost/movie/A".to_owned(),
+                published: date,
+                updated: date,
+                summary: None,
+            }],
+        };
+
+        let xml = feed.to_xml();
+        assert!(xml.contains("<updated>2024-01-01T10:00:00+00:00</updated>\n<link rel=\"self\""));
+        assert!(xml.contains("<author>\n<name>Movies &amp; Co</name>\n</author>"));
+        assert!(xml.contains("<title>Tom &amp; Jerry</title>"));
+        assert!(!xml.contains("<summary>"));
+    }
+}
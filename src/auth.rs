@@ -0,0 +1,53 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts},
+};
+
+use crate::{AppError, HttpError, Service};
+
+/// Proof that the request carries the admin token from `ADMIN_TOKEN` as
+/// `Authorization: Bearer <token>`. Without `ADMIN_TOKEN` nobody is admin.
+///
+/// Guards every `/admin` route as a route layer.
+pub struct Admin;
+
+#[async_trait]
+impl FromRequestParts<Service> for Admin {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &Service) -> Result<Self, AppError> {
+        let Some(expected) = std::env::var("ADMIN_TOKEN").ok().filter(|s| !s.is_empty()) else {
+            return Err(HttpError::forbidden("set ADMIN_TOKEN to enable this endpoint").into());
+        };
+
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| HttpError::unauthorized("send the admin token as a bearer token"))?;
+        if !constant_time_eq(token.as_bytes(), expected.as_bytes()) {
+            return Err(HttpError::unauthorized("invalid admin token").into());
+        }
+        Ok(Self)
+    }
+}
+
+/// Compares without returning early, so that the time taken does not tell
+/// how much of the token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_tokens() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The stale window configured in the environment variable `var`, in
+/// seconds, or `default`.
+pub fn stale_window(var: &str, default: Duration) -> Duration {
+    std::env::var(var)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(default)
+}
+
+/// An in-memory cache whose entries expire a fixed time after they were
+/// stored. Expired entries may still be served for a stale window while
+/// they are refreshed, and be kept longer as a last known good value for
+/// when refreshing fails. Clones share the same entries.
+pub struct Cache<K, V> {
+    entries: Arc<Mutex<HashMap<K, Entry<V>>>>,
+    ttl: Duration,
+    stale: Duration,
+    keep: Duration,
+}
+
+/// The state of a cached value.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Lookup<V> {
+    Fresh(V),
+    /// Expired, but within the stale window; serve it and refresh it.
+    Stale(V),
+    /// Past the stale window; refresh it, but it may be served if that fails.
+    LastKnownGood(V),
+    Missing,
+}
+
+struct Entry<V> {
+    value: V,
+    stored_at: Instant,
+}
+
+impl<K: Eq + Hash, V: Clone> Cache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Arc::default(),
+            ttl,
+            stale: Duration::ZERO,
+            keep: Duration::ZERO,
+        }
+    }
+
+    /// Keeps serving expired entries from [`Cache::lookup`] for `stale`.
+    pub fn with_stale(mut self, stale: Duration) -> Self {
+        self.stale = stale;
+        self
+    }
+
+    /// Keeps entries for `keep` after the stale window, as
+    /// [`Lookup::LastKnownGood`].
+    pub fn keep_for(mut self, keep: Duration) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    /// Returns the value for `key`, unless it is missing or expired.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(key)
+            .filter(|entry| entry.stored_at.elapsed() < self.ttl)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Returns the value for `key` and whether it should be refreshed.
+    pub fn lookup(&self, key: &K) -> Lookup<V> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = entries.get(key) else {
+            return Lookup::Missing;
+        };
+
+        let age = entry.stored_at.elapsed();
+        let value = entry.value.clone();
+        if age < self.ttl {
+            Lookup::Fresh(value)
+        } else if age < self.ttl + self.stale {
+            Lookup::Stale(value)
+        } else if age < self.ttl + self.stale + self.keep {
+            Lookup::LastKnownGood(value)
+        } else {
+            Lookup::Missing
+        }
+    }
+
+    pub fn remove(&self, key: &K) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove(key);
+    }
+
+    /// Removes every entry for which `keep` returns false.
+    pub fn retain(&self, keep: impl Fn(&K, &V) -> bool) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|key, entry| keep(key, &entry.value));
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let kept = self.ttl + self.stale + self.keep;
+        entries.retain(|_, entry| entry.stored_at.elapsed() < kept);
+        entries.insert(
+            key,
+            Entry {
+                value,
+                stored_at: Instant::now(),
+            },
+        );
+    }
+}
+
+impl<K, V> Clone for Cache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+            ttl: self.ttl,
+            stale: self.stale,
+            keep: self.keep,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_expire() {
+        let cache = Cache::new(Duration::from_millis(20));
+        cache.insert("matrix", 1);
+        assert_eq!(cache.get(&"matrix"), Some(1));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&"matrix"), None);
+    }
+
+    #[test]
+    fn serves_expired_entries_within_the_stale_window() {
+        let cache = Cache::new(Duration::from_millis(20)).with_stale(Duration::from_millis(40));
+        cache.insert("matrix", 1);
+        assert_eq!(cache.lookup(&"matrix"), Lookup::Fresh(1));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.lookup(&"matrix"), Lookup::Stale(1));
+        assert_eq!(cache.get(&"matrix"), None);
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(cache.lookup(&"matrix"), Lookup::Missing);
+    }
+
+    #[test]
+    fn keeps_the_last_known_good_value() {
+        let cache = Cache::new(Duration::from_millis(10)).keep_for(Duration::from_secs(60));
+        cache.insert("matrix", 1);
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.lookup(&"matrix"), Lookup::LastKnownGood(1));
+        assert_eq!(cache.get(&"matrix"), None);
+    }
+
+    #[test]
+    fn removes_entries_by_value() {
+        let cache = Cache::new(Duration::from_secs(60));
+        cache.insert("matrix", 1);
+        cache.insert("top gun", 2);
+
+        cache.retain(|_, value| *value != 1);
+        assert_eq!(cache.get(&"matrix"), None);
+        assert_eq!(cache.get(&"top gun"), Some(2));
+    }
+
+    #[test]
+    fn clones_share_entries() {
+        let cache = Cache::new(Duration::from_secs(60));
+        cache.clone().insert("matrix", 1);
+        assert_eq!(cache.get(&"matrix"), Some(1));
+    }
+}
@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    types::Date32Type, ArrayRef, Date32Array, Float64Array, RecordBatch, StringArray, UInt64Array,
+};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema};
+use axum::{
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, VARY},
+        HeaderMap,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use color_eyre::eyre::Result;
+use serde::Serialize;
+
+use crate::{Movie, MovieResult, NearbyMovie};
+
+/// The media type of the Arrow IPC streaming format.
+const ARROW_STREAM: &str = "application/vnd.apache.arrow.stream";
+
+/// The Arrow fields of a movie, matching [`movie_columns`].
+pub fn movie_fields() -> Vec<Field> {
+    vec![
+        Field::new("title", DataType::Utf8, true),
+        Field::new("released", DataType::Date32, true),
+        Field::new("tagline", DataType::Utf8, true),
+        Field::new("votes", DataType::UInt64, true),
+    ]
+}
+
+/// The properties of the movies, one Arrow array per field.
+pub fn movie_columns(movies: &[&Movie]) -> Vec<ArrayRef> {
+    vec![
+        Arc::new(StringArray::from_iter(
+            movies.iter().map(|movie| movie.title.as_deref()),
+        )),
+        Arc::new(Date32Array::from_iter(
+            movies
+                .iter()
+                .map(|movie| movie.released.map(Date32Type::from_naive_date)),
+        )),
+        Arc::new(StringArray::from_iter(
+            movies.iter().map(|movie| movie.tagline.as_deref()),
+        )),
+        Arc::new(UInt64Array::from_iter(
+            movies
+                .iter()
+                .map(|movie| movie.votes.map(|votes| votes as u64)),
+        )),
+    ]
+}
+
+/// Search and listing results, one row per movie.
+pub fn movie_results(results: &[MovieResult]) -> Result<RecordBatch> {
+    let mut fields = movie_fields();
+    fields.push(Field::new("matched_aka", DataType::Utf8, true));
+
+    let movies = results
+        .iter()
+        .map(|result| &result.movie)
+        .collect::<Vec<_>>();
+    let mut columns = movie_columns(&movies);
+    columns.push(Arc::new(StringArray::from_iter(
+        results.iter().map(|result| result.matched_aka.as_deref()),
+    )));
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+/// Movies near a point, with the filming location and its distance.
+pub fn nearby_movies(results: &[NearbyMovie]) -> Result<RecordBatch> {
+    let mut fields = movie_fields();
+    fields.extend([
+        Field::new("location", DataType::Utf8, false),
+        Field::new("latitude", DataType::Float64, false),
+        Field::new("longitude", DataType::Float64, false),
+        Field::new("distance_km", DataType::Float64, false),
+    ]);
+
+    let movies = results
+        .iter()
+        .map(|result| &result.movie)
+        .collect::<Vec<_>>();
+    let mut columns = movie_columns(&movies);
+    columns.extend::<[ArrayRef; 4]>([
+        Arc::new(StringArray::from_iter_values(
+            results.iter().map(|result| &result.location.name),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            results.iter().map(|result| result.location.latitude),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            results.iter().map(|result| result.location.longitude),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            results.iter().map(|result| result.distance_km),
+        )),
+    ]);
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+/// Answers with an Arrow IPC stream if the client accepts one, and with
+/// JSON otherwise.
+pub fn respond<T: Serialize>(
+    headers: &HeaderMap,
+    results: Vec<T>,
+    to_batch: fn(&[T]) -> Result<RecordBatch>,
+) -> Result<Response> {
+    let vary = [(VARY, "Accept")];
+    if !accepts_arrow(headers) {
+        return Ok((vary, Json(results)).into_response());
+    }
+
+    let batch = to_batch(&results)?;
+    let mut writer = StreamWriter::try_new(Vec::new(), &batch.schema())?;
+    writer.write(&batch)?;
+    writer.finish()?;
+
+    Ok((vary, [(CONTENT_TYPE, ARROW_STREAM)], writer.into_inner()?).into_response())
+}
+
+/// Whether the client asked for Arrow by name. A quality of 0 means the
+/// client does not accept it.
+fn accepts_arrow(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|range| {
+            let mut parts = range.split(';');
+            let media_type = parts.next().unwrap_or_default();
+            let quality = parts
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .map_or(1.0, |(_, q)| q.trim().parse::<f32>().unwrap_or(1.0));
+            media_type.trim().eq_ignore_ascii_case(ARROW_STREAM) && quality > 0.0
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_ipc::reader::StreamReader;
+    use axum::{body::to_bytes, http::HeaderValue};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_with_arrow_when_accepted() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("application/vnd.apache.arrow.stream, application/json;q=0.5"),
+        );
+        let results = vec![MovieResult {
+            movie: Movie {
+                title: Some("The Matrix".to_owned()),
+                votes: Some(3),
+                ..Movie::default()
+            },
+            matched_aka: None,
+        }];
+
+        let response = respond(&headers, results, movie_results).unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], ARROW_STREAM);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let batches = StreamReader::try_new(&body[..], None)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 1);
+        assert_eq!(batches[0].schema().field(0).name(), "title");
+    }
+
+    #[test]
+    fn answers_with_json_by_default() {
+        let response =
+            respond(&HeaderMap::new(), Vec::<MovieResult>::new(), movie_results).unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    }
+
+    #[test]
+    fn does_not_answer_with_arrow_of_quality_zero() {
+        for (accept, arrow) in [
+            ("application/vnd.apache.arrow.stream;q=0.8", true),
+            (
+                "application/vnd.apache.arrow.stream; q=0, application/json",
+                false,
+            ),
+            ("application/vnd.apache.arrow.stream;q=0.0", false),
+            ("application/json", false),
+        ] {
+            let mut headers = HeaderMap::new();
+            headers.insert(ACCEPT, HeaderValue::from_static(accept));
+            assert_eq!(accepts_arrow(&headers), arrow, "{accept}");
+        }
+    }
+}
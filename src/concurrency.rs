@@ -0,0 +1,238 @@
+use axum::http::{
+    header::{ETAG, IF_MATCH, IF_UNMODIFIED_SINCE, LAST_MODIFIED},
+    HeaderMap, HeaderValue,
+};
+use chrono::{DateTime, FixedOffset, Utc};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::HttpError;
+
+/// The state of a node that conditional requests are checked against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Version {
+    pub version: i64,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: Option<DateTime<FixedOffset>>,
+}
+
+impl Version {
+    /// The `ETag` and `Last-Modified` headers describing this version.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Ok(etag) = HeaderValue::from_str(&etag(self.version)) {
+            headers.insert(ETAG, etag);
+        }
+        if let Some(updated_at) = self.updated_at {
+            let date = updated_at
+                .with_timezone(&Utc)
+                .format("%a, %d %b %Y %H:%M:%S GMT")
+                .to_string();
+            if let Ok(date) = HeaderValue::from_str(&date) {
+                headers.insert(LAST_MODIFIED, date);
+            }
+        }
+        headers
+    }
+}
+
+/// The entity tag of a node version.
+pub fn etag(version: i64) -> String {
+    format!("\"{version}\"")
+}
+
+/// The `If-Match` and `If-Unmodified-Since` headers of a request.
+#[derive(Debug, Clone)]
+pub struct Preconditions {
+    if_match: Option<IfMatch>,
+    if_unmodified_since: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Debug, Clone)]
+enum IfMatch {
+    Any,
+    Versions(Vec<i64>),
+}
+
+impl Preconditions {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self> {
+        let if_match =
+            headers
+                .get(IF_MATCH)
+                .map(|value| {
+                    value.to_str().ok().and_then(parse_if_match).ok_or_else(|| {
+                        HttpError::bad_request("If-Match must hold entity tags or *")
+                    })
+                })
+                .transpose()?;
+
+        let if_unmodified_since = headers
+            .get(IF_UNMODIFIED_SINCE)
+            .map(|value| {
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                    .ok_or_else(|| {
+                        HttpError::bad_request("If-Unmodified-Since must be an HTTP date")
+                    })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            if_match,
+            if_unmodified_since,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.if_match.is_none() && self.if_unmodified_since.is_none()
+    }
+
+    /// Fails with 412 if the node no longer is in the state the client
+    /// based its request on. As in RFC 9110, `If-Unmodified-Since` is only
+    /// considered without `If-Match`.
+    pub fn evaluate(&self, current: &Version) -> Result<()> {
+        let holds = match (&self.if_match, self.if_unmodified_since) {
+            (Some(IfMatch::Any), _) => true,
+            (Some(IfMatch::Versions(versions)), _) => versions.contains(&current.version),
+            (None, Some(since)) => current
+                .updated_at
+                .is_none_or(|updated_at| updated_at.timestamp() <= since.timestamp()),
+            (None, None) => true,
+        };
+
+        if !holds {
+            return Err(HttpError::precondition_failed(format!(
+                "the node was changed, its current version is {}",
+                current.version
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Parses `*` or a list of strong entity tags. Weak tags never match for
+/// `If-Match` and are skipped.
+fn parse_if_match(value: &str) -> Option<IfMatch> {
+    if value.trim() == "*" {
+        return Some(IfMatch::Any);
+    }
+
+    let mut versions = Vec::new();
+    for tag in value.split(',').map(str::trim) {
+        if tag.starts_with("W/") {
+            continue;
+        }
+        versions.push(tag.strip_prefix('"')?.strip_suffix('"')?.parse().ok()?);
+    }
+    Some(IfMatch::Versions(versions))
+}
+
+/// Fails with 428 unless the client sent the version it is changing or a
+/// conditional header.
+pub fn require(version: Option<i64>, preconditions: &Preconditions) -> Result<()> {
+    if version.is_none() && preconditions.is_empty() {
+        return Err(HttpError::precondition_required(
+            "send the version you are changing in the body, or If-Match or If-Unmodified-Since",
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Fails with 409 if the node was changed since the client read it.
+pub fn check(current: i64, expected: i64) -> Result<()> {
+    if current != expected {
+        return Err(HttpError::conflict(format!(
+            "expected version {expected} but the current version is {current}"
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preconditions(headers: &[(&'static str, &'static str)]) -> Preconditions {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(*name, HeaderValue::from_static(value));
+        }
+        Preconditions::from_headers(&map).unwrap()
+    }
+
+    fn version(version: i64, updated_at: &str) -> Version {
+        Version {
+            version,
+            updated_at: Some(DateTime::parse_from_rfc3339(updated_at).unwrap()),
+        }
+    }
+
+    #[test]
+    fn if_match_compares_versions() {
+        let current = version(3, "2024-01-01T10:00:00Z");
+        assert!(preconditions(&[("if-match", "\"3\"")])
+            .evaluate(&current)
+            .is_ok());
+        assert!(preconditions(&[("if-match", "\"2\", \"3\"")])
+            .evaluate(&current)
+            .is_ok());
+        assert!(preconditions(&[("if-match", "*")])
+            .evaluate(&current)
+            .is_ok());
+        assert!(preconditions(&[("if-match", "\"2\"")])
+            .evaluate(&current)
+            .is_err());
+        assert!(preconditions(&[("if-match", "W/\"3\"")])
+            .evaluate(&current)
+            .is_err());
+    }
+
+    #[test]
+    fn if_unmodified_since_compares_seconds() {
+        let current = version(3, "2024-01-01T10:00:00.500Z");
+        assert!(
+            preconditions(&[("if-unmodified-since", "Mon, 01 Jan 2024 10:00:00 GMT")])
+                .evaluate(&current)
+                .is_ok()
+        );
+        assert!(
+            preconditions(&[("if-unmodified-since", "Mon, 01 Jan 2024 09:59:59 GMT")])
+                .evaluate(&current)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn if_match_takes_precedence() {
+        let current = version(3, "2024-01-01T10:00:00Z");
+        assert!(preconditions(&[
+            ("if-match", "\"3\""),
+            ("if-unmodified-since", "Sun, 31 Dec 2023 00:00:00 GMT"),
+        ])
+        .evaluate(&current)
+        .is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_MATCH, HeaderValue::from_static("3"));
+        assert!(Preconditions::from_headers(&headers).is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_UNMODIFIED_SINCE, HeaderValue::from_static("yesterday"));
+        assert!(Preconditions::from_headers(&headers).is_err());
+    }
+
+    #[test]
+    fn formats_last_modified_as_http_date() {
+        let headers = version(3, "2024-01-01T11:00:00+01:00").headers();
+        assert_eq!(headers[ETAG], "\"3\"");
+        assert_eq!(headers[LAST_MODIFIED], "Mon, 01 Jan 2024 10:00:00 GMT");
+    }
+}
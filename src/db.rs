@@ -0,0 +1,350 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{eyre, Report, Result, WrapErr as _};
+use neo4rs::{Config, ConfigBuilder, Graph};
+use tracing::{info, warn};
+
+use crate::tx::{self, Unreachable};
+
+/// After this many requests in a row could not reach the server, the
+/// breaker opens and requests fail right away.
+const FAILURE_THRESHOLD: u32 = 3;
+/// How long the breaker stays open before one request may try again.
+const OPEN_FOR: Duration = Duration::from_secs(30);
+
+/// A handle to the database that can replace its connection pool when the
+/// credentials change.
+#[derive(Clone)]
+pub struct Db {
+    current: Arc<RwLock<Current>>,
+    reconnect: Arc<tokio::sync::Mutex<()>>,
+    breaker: Breaker,
+}
+
+struct Current {
+    graph: Graph,
+    generation: u64,
+}
+
+impl Db {
+    pub async fn connect() -> Result<Self> {
+        let graph = Graph::connect(config()?).await?;
+        Ok(Self {
+            current: Arc::new(RwLock::new(Current {
+                graph,
+                generation: 0,
+            })),
+            reconnect: Arc::default(),
+            breaker: Breaker::new(FAILURE_THRESHOLD, OPEN_FOR),
+        })
+    }
+
+    fn current(&self) -> (Graph, u64) {
+        let current = self.current.read().unwrap();
+        (current.graph.clone(), current.generation)
+    }
+
+    /// Runs `work` against the current connection pool.
+    ///
+    /// If the server rejects the credentials, they are read again, the pool
+    /// is rebuilt and `work` is run once more. While the server cannot be
+    /// reached, `work` is not run at all and fails with [`Unreachable`].
+    pub async fn with_graph<T, F, Fut>(&self, work: F) -> Result<T>
+    where
+        F: Fn(Graph) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if !self.breaker.allows() {
+            return Err(Unreachable(
+                "the database cannot be reached, not trying again yet".to_owned(),
+            )
+            .into());
+        }
+        let result = self.with_current_graph(work).await;
+        self.breaker.record(&result);
+        result
+    }
+
+    async fn with_current_graph<T, F, Fut>(&self, work: F) -> Result<T>
+    where
+        F: Fn(Graph) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let (graph, generation) = self.current();
+        match work(graph).await {
+            Err(err) if is_auth_failure(&err) => {
+                warn!("authentication failed, reloading credentials: {err}");
+                let graph = self.reconnect(generation).await?;
+                work(graph).await
+            }
+            result => result,
+        }
+    }
+
+    async fn reconnect(&self, failed: u64) -> Result<Graph> {
+        let _guard = self.reconnect.lock().await;
+
+        // another request may have reconnected while we were waiting
+        let (graph, generation) = self.current();
+        if generation != failed {
+            return Ok(graph);
+        }
+
+        let graph = Graph::connect(config()?).await?;
+        let mut current = self.current.write().unwrap();
+        current.graph = graph.clone();
+        current.generation += 1;
+        info!(
+            generation = current.generation,
+            "reconnected with new credentials"
+        );
+
+        Ok(graph)
+    }
+}
+
+/// Counts requests in a row that could not reach the server. Past the
+/// threshold it opens, and after a while lets a single request through to
+/// find out whether the server is back.
+#[derive(Clone)]
+struct Breaker {
+    state: Arc<Mutex<BreakerState>>,
+    threshold: u32,
+    open_for: Duration,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl Breaker {
+    fn new(threshold: u32, open_for: Duration) -> Self {
+        Self {
+            state: Arc::default(),
+            threshold,
+            open_for,
+        }
+    }
+
+    /// Whether a request may go to the server.
+    fn allows(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.opened_at {
+            Some(opened_at) if opened_at.elapsed() < self.open_for => false,
+            Some(_) => {
+                // half-open: this request tries, the others keep failing
+                // until it is done
+                state.opened_at = Some(Instant::now());
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn record<T>(&self, result: &Result<T>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Err(err) if tx::is_connection_failure(err) => {
+                state.failures += 1;
+                if state.failures >= self.threshold {
+                    if state.opened_at.is_none() {
+                        warn!(failures = state.failures, "the database cannot be reached");
+                    }
+                    state.opened_at = Some(Instant::now());
+                }
+            }
+            _ => {
+                if state.opened_at.is_some() {
+                    info!("the database can be reached again");
+                }
+                *state = BreakerState::default();
+            }
+        }
+    }
+}
+
+fn is_auth_failure(err: &Report) -> bool {
+    match err.downcast_ref::<neo4rs::Error>() {
+        Some(neo4rs::Error::AuthenticationError(_)) => true,
+        Some(neo4rs::Error::UnexpectedMessage(msg)) => {
+            msg.contains("Neo.ClientError.Security.Unauthorized")
+        }
+        _ => false,
+    }
+}
+
+fn config() -> Result<Config> {
+    const DEFAULT_URL: &str = "neo4j+s://demo.neo4jlabs.com";
+    const DEFAULT_DATABASE: &str = "movies";
+    const DEFAULT_USER: &str = "movies";
+    const DEFAULT_PASS: &str = "movies";
+
+    let uri = std::env::var("NEO4J_URI")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| DEFAULT_URL.to_owned());
+
+    let mut config = ConfigBuilder::new()
+        .uri(&uri)
+        .user(
+            std::env::var("NEO4J_USER")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .as_deref()
+                .unwrap_or(DEFAULT_USER),
+        )
+        .password(password()?.as_deref().unwrap_or(DEFAULT_PASS))
+        .db(std::env::var("NEO4J_DATABASE")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .as_deref()
+            .unwrap_or(DEFAULT_DATABASE));
+
+    let ca_cert = std::env::var("NEO4J_CA_CERT")
+        .ok()
+        .filter(|s| !s.is_empty());
+    if let Some(ca_cert) = trusted_certificate(&uri, ca_cert)? {
+        // neo4rs adds this file to the trusted roots, despite the name
+        config = config.with_client_certificate(ca_cert);
+    }
+
+    Ok(config.build()?)
+}
+
+/// Reads the password, preferring `NEO4J_PASSWORD_FILE` over `NEO4J_PASSWORD`.
+///
+/// The file is read again on every reconnect, so a mounted secret can be
+/// rotated while the application is running.
+fn password() -> Result<Option<String>> {
+    if let Some(path) = std::env::var("NEO4J_PASSWORD_FILE")
+        .ok()
+        .filter(|s| !s.is_empty())
+    {
+        let password = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("cannot read NEO4J_PASSWORD_FILE file {path}"))?;
+        return Ok(Some(password.trim_end_matches(['\r', '\n']).to_owned()));
+    }
+
+    Ok(std::env::var("NEO4J_PASSWORD")
+        .ok()
+        .filter(|s| !s.is_empty()))
+}
+
+/// Checks the CA certificate setting against the TLS mode of the URI scheme.
+///
+/// The driver always verifies server certificates, so `+ssc` schemes
+/// (self-signed certificates) need the server certificate to be trusted
+/// explicitly through `NEO4J_CA_CERT`.
+fn trusted_certificate(uri: &str, ca_cert: Option<String>) -> Result<Option<String>> {
+    let scheme = uri.split_once("://").map_or("", |(scheme, _)| scheme);
+    let encrypted = scheme.ends_with("+s") || scheme.ends_with("+ssc");
+
+    match ca_cert {
+        Some(path) if encrypted => {
+            std::fs::metadata(&path)
+                .wrap_err_with(|| format!("cannot read NEO4J_CA_CERT file {path}"))?;
+            Ok(Some(path))
+        }
+        Some(path) => {
+            warn!("ignoring NEO4J_CA_CERT={path}, the {scheme}:// scheme does not use TLS");
+            Ok(None)
+        }
+        None if scheme.ends_with("+ssc") => Err(eyre!(
+            "the {scheme}:// scheme requires NEO4J_CA_CERT to point to the server certificate"
+        )),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unreachable() -> Result<()> {
+        Err(neo4rs::Error::ConnectionError.into())
+    }
+
+    #[test]
+    fn opens_after_consecutive_connection_failures() {
+        let breaker = Breaker::new(2, Duration::from_secs(60));
+        breaker.record(&unreachable());
+        breaker.record(&Ok(()));
+        breaker.record(&unreachable());
+        assert!(breaker.allows());
+
+        breaker.record(&unreachable());
+        assert!(!breaker.allows());
+    }
+
+    #[test]
+    fn counts_timeouts_as_connection_failures() {
+        let breaker = Breaker::new(1, Duration::from_secs(60));
+        let timed_out: Result<()> =
+            Err(Unreachable("query graph timed out after 10s".to_owned()).into());
+        breaker.record(&timed_out);
+        assert!(!breaker.allows());
+    }
+
+    #[test]
+    fn lets_one_request_try_again() {
+        let breaker = Breaker::new(1, Duration::from_millis(10));
+        breaker.record(&unreachable());
+        assert!(!breaker.allows());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(breaker.allows());
+        assert!(!breaker.allows());
+
+        breaker.record(&Ok(()));
+        assert!(breaker.allows());
+    }
+
+    #[test]
+    fn recognizes_rejected_credentials() {
+        let unauthorized = neo4rs::Error::UnexpectedMessage(
+            "Failure(Neo.ClientError.Security.Unauthorized, The client is unauthorized)".to_owned(),
+        );
+        assert!(is_auth_failure(&unauthorized.into()));
+
+        let forbidden = neo4rs::Error::UnexpectedMessage(
+            "Failure(Neo.ClientError.Security.Forbidden, Write operations are not allowed)"
+                .to_owned(),
+        );
+        assert!(!is_auth_failure(&forbidden.into()));
+        assert!(!is_auth_failure(&neo4rs::Error::ConnectionError.into()));
+    }
+
+    #[test]
+    fn trusts_certificates_only_for_tls_schemes() {
+        let cert = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml").to_owned();
+
+        assert_eq!(
+            trusted_certificate("neo4j+s://db.example.com", Some(cert.clone())).unwrap(),
+            Some(cert.clone())
+        );
+        assert_eq!(
+            trusted_certificate("bolt://localhost:7687", Some(cert)).unwrap(),
+            None
+        );
+        assert_eq!(
+            trusted_certificate("neo4j+s://db.example.com", None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn requires_certificates_for_self_signed_schemes() {
+        assert!(trusted_certificate("bolt+ssc://localhost:7687", None).is_err());
+        assert!(trusted_certificate(
+            "neo4j+s://db.example.com",
+            Some("/no/such/ca.pem".to_owned())
+        )
+        .is_err());
+    }
+}
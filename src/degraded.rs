@@ -0,0 +1,103 @@
+use std::{future::Future, time::Duration};
+
+use axum::http::{header::WARNING, HeaderMap, HeaderValue};
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::tx;
+
+/// How long cached responses are kept to be served while the database
+/// cannot be reached.
+pub const LAST_KNOWN_GOOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+const REVALIDATION_FAILED: &str = "111 - \"Revalidation Failed\"";
+
+/// A response that is either current or, because the database could not
+/// be reached, the last known good one.
+#[derive(Debug, Serialize)]
+pub struct Served<T> {
+    #[serde(flatten)]
+    pub value: T,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub degraded: bool,
+}
+
+impl<T> Served<T> {
+    pub fn current(value: T) -> Self {
+        Self {
+            value,
+            degraded: false,
+        }
+    }
+
+    /// A `Warning` header for degraded responses.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if self.degraded {
+            headers.insert(WARNING, HeaderValue::from_static(REVALIDATION_FAILED));
+        }
+        headers
+    }
+}
+
+/// Refreshes a cached value, falling back to its last known good value if
+/// the database cannot be reached. Other errors are returned as they are.
+pub async fn refresh_or<T>(
+    last_known_good: T,
+    refresh: impl Future<Output = Result<T>>,
+) -> Result<Served<T>> {
+    match refresh.await {
+        Ok(value) => Ok(Served::current(value)),
+        Err(err) if tx::is_connection_failure(&err) => {
+            warn!("serving the last known good response: {err:?}");
+            Ok(Served {
+                value: last_known_good,
+                degraded: true,
+            })
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::eyre::eyre;
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn flags_fallbacks_as_degraded() {
+        let served = refresh_or(json!({ "title": "The Matrix" }), async {
+            Err(neo4rs::Error::ConnectionError.into())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&served).unwrap(),
+            json!({ "title": "The Matrix", "degraded": true })
+        );
+        assert_eq!(served.headers()[WARNING], REVALIDATION_FAILED);
+
+        let served = refresh_or(json!({}), async { Ok(json!({ "title": "Cast Away" })) })
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&served).unwrap(),
+            json!({ "title": "Cast Away" })
+        );
+        assert!(served.headers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn returns_other_errors() {
+        let served = refresh_or(json!({ "title": "The Matrix" }), async {
+            Err::<serde_json::Value, _>(eyre!("invalid input"))
+        })
+        .await;
+
+        assert!(served.is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use std::ops::Sub;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+    Json,
+};
+use color_eyre::eyre::Result;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    queries::{self, Access},
+    rows,
+    tx::{self, Tx},
+    AppError, HttpError, Service,
+};
+
+const HEADER: &str = "x-dry-run";
+
+/// Whether a mutation was requested as a dry run, with `?dry_run=true` or
+/// an `X-Dry-Run: true` header.
+#[derive(Debug, Clone, Copy)]
+pub struct DryRun(pub bool);
+
+#[derive(Debug, Deserialize)]
+struct DryRunParam {
+    dry_run: Option<bool>,
+}
+
+#[async_trait]
+impl FromRequestParts<Service> for DryRun {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, service: &Service) -> Result<Self, AppError> {
+        let Query(param) = Query::<DryRunParam>::from_request_parts(parts, service)
+            .await
+            .map_err(|_| HttpError::bad_request("dry_run must be true or false"))?;
+        let header = parts
+            .headers
+            .get(HEADER)
+            .map(|value| {
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| value.parse::<bool>().ok())
+                    .ok_or_else(|| HttpError::bad_request("X-Dry-Run must be true or false"))
+            })
+            .transpose()?;
+
+        Ok(Self(param.dry_run.or(header).unwrap_or(false)))
+    }
+}
+
+/// The outcome of a mutation that was either committed or only previewed.
+#[derive(Debug)]
+pub enum Written<T> {
+    Committed(T),
+    Previewed(Preview<T>),
+}
+
+impl<T: Serialize> Written<T> {
+    /// Answers with `committed` for the result of a committed mutation, and
+    /// with the preview as JSON otherwise.
+    pub fn respond<R: IntoResponse>(self, committed: impl FnOnce(T) -> R) -> Response {
+        match self {
+            Self::Committed(value) => committed(value).into_response(),
+            Self::Previewed(preview) => Json(preview).into_response(),
+        }
+    }
+}
+
+/// What a rolled back mutation would have returned and changed.
+#[derive(Debug, Serialize)]
+pub struct Preview<T> {
+    #[serde(rename = "dryRun")]
+    dry_run: bool,
+    impact: Impact,
+    result: T,
+}
+
+/// The net change in the number of nodes and relationships. neo4rs does
+/// not expose the counters of the result summary, so the graph is counted
+/// before and after the mutation instead; properties that were set are not
+/// part of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Impact {
+    nodes: i64,
+    relationships: i64,
+}
+
+impl Sub for Impact {
+    type Output = Self;
+
+    fn sub(self, before: Self) -> Self {
+        Self {
+            nodes: self.nodes - before.nodes,
+            relationships: self.relationships - before.relationships,
+        }
+    }
+}
+
+impl Service {
+    /// Runs `work` in a write transaction like `execute_write`, or for a dry
+    /// run in one that is rolled back, reporting what it would have done.
+    pub async fn execute_mutation<T, F>(&self, dry_run: DryRun, work: F) -> Result<Written<T>>
+    where
+        T: Send,
+        F: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<T>> + Sync,
+    {
+        if !dry_run.0 {
+            return self.execute_write(work).await.map(Written::Committed);
+        }
+
+        let (before, result, after) = self
+            .db
+            .with_graph(|graph| {
+                let work = &work;
+                async move {
+                    tx::run_measured(&graph, Access::Write, |tx| Box::pin(graph_size(tx)), work)
+                        .await
+                }
+            })
+            .await?;
+        let preview = Preview {
+            dry_run: true,
+            impact: after - before,
+            result,
+        };
+        Ok(Written::Previewed(preview))
+    }
+}
+
+async fn graph_size(tx: &mut Tx) -> Result<Impact> {
+    let rows = tx.execute(queries::GRAPH_SIZE.name, []).await?;
+    rows::from_row(&rows[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn previews_the_result_and_impact() {
+        let before = Impact {
+            nodes: 10,
+            relationships: 20,
+        };
+        let after = Impact {
+            nodes: 9,
+            relationships: 17,
+        };
+        let preview = Preview {
+            dry_run: true,
+            impact: after - before,
+            result: json!({ "deleted": 1 }),
+        };
+
+        assert_eq!(
+            serde_json::to_value(preview).unwrap(),
+            json!({
+                "dryRun": true,
+                "impact": { "nodes": -1, "relationships": -3 },
+                "result": { "deleted": 1 },
+            })
+        );
+    }
+}
@@ -0,0 +1,395 @@
+use std::{fs::File, path::PathBuf, sync::Arc};
+
+use arrow_array::{
+    builder::{ListBuilder, StringBuilder},
+    ArrayRef, Int64Array, RecordBatch, StringArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::Datelike as _;
+use color_eyre::eyre::{Result, WrapErr as _};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt as _, TryStreamExt as _,
+};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use serde::Deserialize;
+use tracing::{info, instrument};
+
+use crate::{
+    columnar, lenient,
+    limits::{self, Limited},
+    queries, rows, AppError, Movie, MovieResult, Search, Service,
+};
+
+const CSV_HEADER: &str = "title,released,tagline,votes\r\n";
+
+/// Rows per Parquet record batch.
+const BATCH_SIZE: usize = 8192;
+
+pub fn routes() -> Router<Service> {
+    Router::new().route("/search/export", get(export_csv))
+}
+
+async fn export_csv(
+    Query(search): Query<Search>,
+    Limited(limit, _): Limited<limits::Export>,
+    State(service): State<Service>,
+) -> Result<Response, AppError> {
+    let filename = filename(&search.q);
+    let body = service.export_csv(search, limit).await?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8".to_owned()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+impl Service {
+    /// Streams the search results as CSV, one line per row as it arrives
+    /// from the database.
+    #[instrument(skip(self))]
+    async fn export_csv(&self, search: Search, limit: i64) -> Result<Body> {
+        let rows = self
+            .execute(
+                queries::SEARCH_MOVIES.name,
+                [("part", search.q.into()), ("limit", limit.into())],
+            )
+            .await?;
+
+        let lines = rows::map::<MovieResult>(rows)
+            .map_ok(|result| Bytes::from(csv_line(&result.movie)))
+            .map_err(|err| std::io::Error::other(err.to_string()));
+        let header = stream::once(async { Ok(Bytes::from_static(CSV_HEADER.as_bytes())) });
+
+        Ok(Body::from_stream(header.chain(lines)))
+    }
+}
+
+impl Service {
+    /// Writes all movies, people and the relationships between nodes into
+    /// `movies.parquet`, `people.parquet` and `relationships.parquet` in `dir`.
+    #[instrument(skip(self))]
+    pub async fn export_parquet(&self, dir: PathBuf) -> Result<()> {
+        std::fs::create_dir_all(&dir)
+            .wrap_err_with(|| format!("could not create {}", dir.display()))?;
+
+        let rows = self.execute(queries::EXPORT_MOVIES.name, []).await?;
+        let movies = write_parquet(
+            dir.join("movies.parquet"),
+            rows::map::<ExportMovie>(rows),
+            movie_batch,
+        )
+        .await?;
+
+        let rows = self.execute(queries::EXPORT_PEOPLE.name, []).await?;
+        let people = write_parquet(
+            dir.join("people.parquet"),
+            rows::map::<ExportPerson>(rows),
+            person_batch,
+        )
+        .await?;
+
+        let rows = self.execute(queries::EXPORT_RELATIONSHIPS.name, []).await?;
+        let relationships = write_parquet(
+            dir.join("relationships.parquet"),
+            rows::map::<ExportRelationship>(rows),
+            relationship_batch,
+        )
+        .await?;
+
+        info!(movies, people, relationships, dir = %dir.display(), "exported the graph to Parquet");
+        Ok(())
+    }
+}
+
+/// Writes the rows into a Parquet file, one record batch per
+/// [`BATCH_SIZE`] rows, and returns how many rows were written.
+async fn write_parquet<T>(
+    path: PathBuf,
+    mut rows: BoxStream<'static, Result<T>>,
+    to_batch: fn(&[T]) -> Result<RecordBatch>,
+) -> Result<usize> {
+    let file =
+        File::create(&path).wrap_err_with(|| format!("could not create {}", path.display()))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+
+    // an empty batch carries the schema
+    let schema = to_batch(&[])?.schema();
+    let mut writer = ArrowWriter::try_new(file, schema, Some(properties))?;
+
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut written = 0;
+    while let Some(row) = rows.next().await {
+        batch.push(row?);
+        if batch.len() == BATCH_SIZE {
+            writer.write(&to_batch(&batch)?)?;
+            written += batch.len();
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        writer.write(&to_batch(&batch)?)?;
+        written += batch.len();
+    }
+
+    writer.close()?;
+    Ok(written)
+}
+
+fn movie_batch(movies: &[ExportMovie]) -> Result<RecordBatch> {
+    let mut fields = vec![Field::new("id", DataType::Utf8, false)];
+    fields.extend(columnar::movie_fields());
+
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(StringArray::from_iter_values(
+        movies.iter().map(|m| &m.id),
+    ))];
+    columns.extend(columnar::movie_columns(
+        &movies.iter().map(|m| &m.movie).collect::<Vec<_>>(),
+    ));
+
+    Ok(RecordBatch::try_new(schema(fields), columns)?)
+}
+
+fn person_batch(people: &[ExportPerson]) -> Result<RecordBatch> {
+    let fields = vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("name", DataType::Utf8, true),
+        Field::new("born", DataType::Int64, true),
+    ];
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(people.iter().map(|p| &p.id))),
+        Arc::new(StringArray::from_iter(
+            people.iter().map(|p| p.name.as_deref()),
+        )),
+        Arc::new(Int64Array::from_iter(
+            people.iter().map(|p| p.born.map(|born| born as i64)),
+        )),
+    ];
+
+    Ok(RecordBatch::try_new(schema(fields), columns)?)
+}
+
+fn relationship_batch(relationships: &[ExportRelationship]) -> Result<RecordBatch> {
+    let mut roles = ListBuilder::new(StringBuilder::new());
+    for relationship in relationships {
+        match &relationship.roles {
+            Some(list) => {
+                for role in list {
+                    roles.values().append_value(role);
+                }
+                roles.append(true);
+            }
+            None => roles.append(false),
+        }
+    }
+
+    let fields = vec![
+        Field::new("source", DataType::Utf8, false),
+        Field::new("target", DataType::Utf8, false),
+        Field::new("type", DataType::Utf8, false),
+        Field::new(
+            "roles",
+            DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+            true,
+        ),
+    ];
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            relationships.iter().map(|r| &r.source),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            relationships.iter().map(|r| &r.target),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            relationships.iter().map(|r| &r.kind),
+        )),
+        Arc::new(roles.finish()),
+    ];
+
+    Ok(RecordBatch::try_new(schema(fields), columns)?)
+}
+
+fn schema(fields: Vec<Field>) -> SchemaRef {
+    Arc::new(Schema::new(fields))
+}
+
+fn csv_line(movie: &Movie) -> String {
+    let fields = [
+        movie.title.clone().unwrap_or_default(),
+        movie
+            .released
+            .map(|released| released.year().to_string())
+            .unwrap_or_default(),
+        movie.tagline.clone().unwrap_or_default(),
+        movie
+            .votes
+            .map(|votes| votes.to_string())
+            .unwrap_or_default(),
+    ];
+    let mut line = fields
+        .iter()
+        .map(|f| csv_field(f))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+/// Quotes a field as in RFC 4180, if it contains a separator, a quote or a
+/// line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// A file name derived from the search, restricted to characters that are
+/// safe in a header and on any file system.
+fn filename(q: &str) -> String {
+    let slug = q
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() {
+        "movies.csv".to_owned()
+    } else {
+        format!("movies-{slug}.csv")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportMovie {
+    id: String,
+    movie: Movie,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportPerson {
+    id: String,
+    name: Option<String>,
+    #[serde(default, deserialize_with = "lenient::count")]
+    born: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportRelationship {
+    source: String,
+    target: String,
+    #[serde(rename = "type")]
+    kind: String,
+    roles: Option<Vec<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use parquet::file::reader::{FileReader as _, SerializedFileReader};
+
+    use super::*;
+
+    #[test]
+    fn quotes_fields_that_need_it() {
+        let movie = Movie {
+            title: Some("Hello, \"World\"".to_owned()),
+            released: NaiveDate::from_ymd_opt(1999, 1, 1),
+            tagline: Some("two\nlines".to_owned()),
+            votes: Some(3),
+            ..Movie::default()
+        };
+        assert_eq!(
+            csv_line(&movie),
+            "\"Hello, \"\"World\"\"\",1999,\"two\nlines\",3\r\n"
+        );
+        assert_eq!(csv_line(&Movie::default()), ",,,\r\n");
+    }
+
+    #[tokio::test]
+    async fn writes_parquet_batches() {
+        let relationships = vec![
+            ExportRelationship {
+                source: "4:p:1".to_owned(),
+                target: "4:m:1".to_owned(),
+                kind: "ACTED_IN".to_owned(),
+                roles: Some(vec!["Neo".to_owned()]),
+            },
+            ExportRelationship {
+                source: "4:p:2".to_owned(),
+                target: "4:m:1".to_owned(),
+                kind: "DIRECTED".to_owned(),
+                roles: None,
+            },
+        ];
+        let path =
+            std::env::temp_dir().join(format!("relationships-{}.parquet", std::process::id()));
+
+        let written = write_parquet(
+            path.clone(),
+            stream::iter(relationships.into_iter().map(Ok)).boxed(),
+            relationship_batch,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(written, 2);
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn builds_movie_and_person_batches() {
+        let movie = ExportMovie {
+            id: "4:m:1".to_owned(),
+            movie: Movie {
+                title: Some("The Matrix".to_owned()),
+                released: NaiveDate::from_ymd_opt(1999, 3, 31),
+                ..Movie::default()
+            },
+        };
+        assert_eq!(movie_batch(&[movie]).unwrap().num_columns(), 5);
+
+        let person = ExportPerson {
+            id: "4:p:1".to_owned(),
+            name: None,
+            born: Some(1964),
+        };
+        assert_eq!(person_batch(&[person]).unwrap().num_rows(), 1);
+    }
+
+    #[test]
+    fn derives_safe_filenames() {
+        assert_eq!(filename("The Matrix"), "movies-the-matrix.csv");
+        assert_eq!(filename("\"; rm -rf /"), "movies-rm-rf.csv");
+        assert_eq!(filename(""), "movies.csv");
+    }
+}
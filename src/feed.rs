@@ -0,0 +1,86 @@
+use axum::{
+    extract::State,
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+        HeaderMap,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, FixedOffset};
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::{
+    atom::{Entry, Feed},
+    queries, rows,
+    sitemap::{base_url, movie_url},
+    AppError, Service,
+};
+
+const FEED_SIZE: i64 = 20;
+
+pub fn routes() -> Router<Service> {
+    Router::new().route("/feed.atom", get(feed))
+}
+
+async fn feed(State(service): State<Service>, headers: HeaderMap) -> Result<Response, AppError> {
+    let base = base_url(&headers)?;
+    let feed = service.feed(base).await?;
+    Ok((
+        [
+            (CONTENT_TYPE, "application/atom+xml"),
+            (CACHE_CONTROL, "public, max-age=300"),
+        ],
+        feed.to_xml(),
+    )
+        .into_response())
+}
+
+impl Service {
+    /// The most recently created movies. Movies imported without a
+    /// `createdAt` property are left out, since it is unknown when they
+    /// were added.
+    #[instrument(skip(self))]
+    async fn feed(&self, base: String) -> Result<Feed> {
+        let rows = self
+            .execute(queries::NEWEST_MOVIES.name, [("limit", FEED_SIZE.into())])
+            .await?;
+        let movies = rows::collect::<NewMovie>(rows).await?;
+
+        let link = format!("{base}/feed.atom");
+        Ok(Feed {
+            id: link.clone(),
+            title: "Newest movies".to_owned(),
+            author: "Neo4j Movies".to_owned(),
+            link,
+            entries: movies
+                .into_iter()
+                .map(|movie| {
+                    let url = movie_url(&base, &movie.id);
+                    Entry {
+                        id: url.clone(),
+                        title: movie.title,
+                        link: url,
+                        published: movie.created_at,
+                        updated: movie.updated_at.unwrap_or(movie.created_at),
+                        summary: movie.tagline,
+                    }
+                })
+                .collect(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NewMovie {
+    id: String,
+    title: String,
+    tagline: Option<String>,
+    #[serde(rename = "createdAt")]
+    created_at: DateTime<FixedOffset>,
+    #[serde(rename = "updatedAt")]
+    updated_at: Option<DateTime<FixedOffset>>,
+}
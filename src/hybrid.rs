@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use crate::{
+    limits::{self, Limited},
+    queries, rows,
+    semantic::ScoredMovie,
+    AppError, HttpError, Movie, Service,
+};
+
+/// Dampens the influence of the top ranks, the usual choice for reciprocal
+/// rank fusion.
+const RANK_CONSTANT: f64 = 60.0;
+
+/// Lucene query syntax characters that are matched literally.
+const LUCENE_SPECIAL: &[char] = &[
+    '+', '-', '&', '|', '!', '(', ')', '{', '}', '[', ']', '^', '"', '~', '*', '?', ':', '\\', '/',
+];
+
+pub fn routes() -> Router<Service> {
+    Router::new().route("/search/hybrid", get(hybrid_search))
+}
+
+async fn hybrid_search(
+    Query(search): Query<HybridSearch>,
+    Limited(limit, _): Limited<limits::Semantic>,
+    State(service): State<Service>,
+) -> Result<Json<Vec<HybridMovie>>, AppError> {
+    Ok(Json(service.hybrid_search(search, limit).await?))
+}
+
+impl Service {
+    /// Runs the full-text and the vector search and merges both rankings
+    /// with reciprocal rank fusion. Without an embeddings provider only the
+    /// full-text ranking is used.
+    #[instrument(skip(self))]
+    async fn hybrid_search(&self, search: HybridSearch, limit: i64) -> Result<Vec<HybridMovie>> {
+        let q = search.q.trim();
+        if q.is_empty() {
+            return Err(HttpError::bad_request("q must not be blank").into());
+        }
+        let weights = Weights {
+            fulltext: search.fulltext_weight.unwrap_or(1.0),
+            vector: search.vector_weight.unwrap_or(1.0),
+        };
+        if ![weights.fulltext, weights.vector]
+            .iter()
+            .all(|w| w.is_finite() && *w >= 0.0)
+        {
+            return Err(HttpError::bad_request("weights must not be negative").into());
+        }
+
+        // both rankings are deeper than the result, so that movies found by
+        // only one of them can still make it
+        let candidates = self.limits.semantic.max;
+        let (fulltext, vector) = tokio::try_join!(
+            async {
+                let rows = self
+                    .execute(
+                        queries::FULLTEXT_SEARCH.name,
+                        [
+                            ("query", escape_lucene(q).into()),
+                            ("limit", candidates.into()),
+                        ],
+                    )
+                    .await?;
+                rows::collect::<ScoredMovie>(rows).await
+            },
+            async {
+                if self.embedder.is_none() {
+                    return Ok(Vec::new());
+                }
+                let embedding = self.embed_query(q).await?;
+                let rows = self
+                    .execute(
+                        queries::SEMANTIC_SEARCH.name,
+                        [
+                            ("limit", candidates.into()),
+                            ("embedding", embedding.into()),
+                        ],
+                    )
+                    .await?;
+                rows::collect::<ScoredMovie>(rows).await
+            },
+        )?;
+
+        let movies = fuse(fulltext, vector, weights, limit as usize);
+
+        debug!(?movies);
+
+        Ok(movies)
+    }
+}
+
+/// Merges two rankings, scoring each movie with the sum of
+/// `weight / (RANK_CONSTANT + rank)` over the rankings it appears in.
+fn fuse(
+    fulltext: Vec<ScoredMovie>,
+    vector: Vec<ScoredMovie>,
+    weights: Weights,
+    limit: usize,
+) -> Vec<HybridMovie> {
+    let mut fused: Vec<HybridMovie> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for (weight, results, is_vector) in [
+        (weights.fulltext, fulltext, false),
+        (weights.vector, vector, true),
+    ] {
+        for (index, ScoredMovie { id, movie, score }) in results.into_iter().enumerate() {
+            let rank = index + 1;
+            let component = Component {
+                rank,
+                score,
+                contribution: weight / (RANK_CONSTANT + rank as f64),
+            };
+
+            let position = *positions.entry(id).or_insert_with(|| {
+                fused.push(HybridMovie {
+                    movie,
+                    score: 0.0,
+                    fulltext: None,
+                    vector: None,
+                });
+                fused.len() - 1
+            });
+
+            let entry = &mut fused[position];
+            entry.score += component.contribution;
+            if is_vector {
+                entry.vector = Some(component);
+            } else {
+                entry.fulltext = Some(component);
+            }
+        }
+    }
+
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+    fused.truncate(limit);
+    fused
+}
+
+fn escape_lucene(q: &str) -> String {
+    let mut escaped = String::with_capacity(q.len());
+    for c in q.chars() {
+        if LUCENE_SPECIAL.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HybridSearch {
+    q: String,
+    fulltext_weight: Option<f64>,
+    vector_weight: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Weights {
+    fulltext: f64,
+    vector: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HybridMovie {
+    movie: Movie,
+    score: f64,
+    fulltext: Option<Component>,
+    vector: Option<Component>,
+}
+
+/// How one ranking contributed to the fused score of a movie.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Component {
+    rank: usize,
+    score: f64,
+    contribution: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scored(id: &str, score: f64) -> ScoredMovie {
+        ScoredMovie {
+            id: id.to_owned(),
+            movie: Movie {
+                title: Some(id.to_owned()),
+                ..Movie::default()
+            },
+            score,
+        }
+    }
+
+    fn titles(movies: &[HybridMovie]) -> Vec<&str> {
+        movies
+            .iter()
+            .map(|m| m.movie.title.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn movies_in_both_rankings_come_first() {
+        let fulltext = vec![scored("a", 3.0), scored("b", 2.0)];
+        let vector = vec![scored("c", 0.9), scored("b", 0.8)];
+        let weights = Weights {
+            fulltext: 1.0,
+            vector: 1.0,
+        };
+
+        let fused = fuse(fulltext, vector, weights, 10);
+
+        assert_eq!(titles(&fused), ["b", "a", "c"]);
+        let b = &fused[0];
+        assert_eq!(b.fulltext.as_ref().unwrap().rank, 2);
+        assert_eq!(b.vector.as_ref().unwrap().rank, 2);
+        assert!((b.score - 2.0 / 62.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn weights_shift_the_ranking() {
+        let fulltext = vec![scored("a", 3.0)];
+        let vector = vec![scored("c", 0.9)];
+        let weights = Weights {
+            fulltext: 0.5,
+            vector: 2.0,
+        };
+
+        let fused = fuse(fulltext, vector, weights, 1);
+
+        assert_eq!(titles(&fused), ["c"]);
+        assert!(fused[0].fulltext.is_none());
+    }
+
+    #[test]
+    fn escapes_lucene_syntax() {
+        assert_eq!(escape_lucene("matrix: reloaded?"), "matrix\\: reloaded\\?");
+    }
+}
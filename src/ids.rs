@@ -0,0 +1,230 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::{header::LOCATION, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use color_eyre::eyre::Result;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use tracing::{instrument, warn};
+
+use crate::{cache::Lookup, queries, rows, tx, AppError, HttpError, Movie, Service};
+
+/// How long the id of a title is cached. Ids never change, titles rarely
+/// do.
+pub const ID_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Movies are addressed by their `id` property; the title routes redirect
+/// there, so links keep working for titles that are awkward in URLs.
+pub fn routes() -> Router<Service> {
+    Router::new()
+        .route("/movie/id/:id", get(movie))
+        .route("/movie/:title", get(redirect_to_id))
+}
+
+/// How a movie is looked up: by its id, or by its title if it has not been
+/// given an id yet, e.g. when it was loaded while the schema was skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MovieKey {
+    Id(String),
+    Title(String),
+}
+
+impl MovieKey {
+    /// The key that addresses `movie`, if it has an id or a title.
+    pub fn of(movie: &Movie) -> Option<Self> {
+        match (&movie.id, &movie.title) {
+            (Some(id), _) => Some(Self::Id(id.clone())),
+            (None, Some(title)) => Some(Self::Title(title.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+async fn movie(
+    Path(id): Path<String>,
+    State(service): State<Service>,
+) -> Result<Response, AppError> {
+    serve(&service, MovieKey::Id(id)).await
+}
+
+/// Redirects to the id of the movie, or serves it right away if it has no
+/// id or its id is not known while the database is unreachable.
+async fn redirect_to_id(
+    Path(title): Path<String>,
+    State(service): State<Service>,
+) -> Result<Response, AppError> {
+    match service.movie_id(title.clone()).await? {
+        Some(id) => Ok(moved_permanently(&movie_path(&id))),
+        None => serve(&service, MovieKey::Title(title)).await,
+    }
+}
+
+async fn serve(service: &Service, key: MovieKey) -> Result<Response, AppError> {
+    let movie = service.movie(key.clone()).await?;
+    if movie.value.title.is_none() {
+        let message = match key {
+            MovieKey::Id(id) => format!("no movie with id {id}"),
+            MovieKey::Title(title) => format!("no movie {title}"),
+        };
+        return Err(HttpError::not_found(message).into());
+    }
+
+    let mut headers = movie.headers();
+    headers.extend(movie.value.current_version().headers());
+    Ok((headers, Json(movie)).into_response())
+}
+
+/// The canonical path of a movie. Generated ids are URL safe, but ids set
+/// through the admin endpoints may not be.
+pub fn movie_path(id: &str) -> String {
+    format!("/movie/id/{}", utf8_percent_encode(id, NON_ALPHANUMERIC))
+}
+
+fn moved_permanently(location: &str) -> Response {
+    (
+        StatusCode::MOVED_PERMANENTLY,
+        [(LOCATION, location.to_owned())],
+    )
+        .into_response()
+}
+
+impl Service {
+    /// The id of the movie titled `title`, from the cache if possible. An id
+    /// that could not be refreshed is still used while the database is
+    /// unreachable. `None` if there is no such movie, it has no id, or its
+    /// id is not known. Fails with 409 if the title is ambiguous.
+    #[instrument(skip(self))]
+    async fn movie_id(&self, title: String) -> Result<Option<String>> {
+        let cached = match self.ids.lookup(&title) {
+            Lookup::Fresh(id) => return Ok(Some(id)),
+            Lookup::Stale(id) | Lookup::LastKnownGood(id) => Some(id),
+            Lookup::Missing => None,
+        };
+
+        let loaded = async {
+            let rows = self
+                .execute(queries::MOVIE_ID.name, [("title", title.clone().into())])
+                .await?;
+            resolve(&title, rows::collect::<MovieId>(rows).await?)
+        };
+        match loaded.await {
+            Ok(Some(id)) => {
+                self.ids.insert(title, id.clone());
+                Ok(Some(id))
+            }
+            Ok(None) => {
+                self.ids.remove(&title);
+                Ok(None)
+            }
+            Err(err) if tx::is_connection_failure(&err) => {
+                warn!("serving the last known id of {title}: {err:?}");
+                Ok(cached)
+            }
+            Err(err) => {
+                self.ids.remove(&title);
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Picks the movie a title refers to: the one that has it as its current
+/// title, else the one that had it before. Several movies sharing either
+/// make the title ambiguous.
+fn resolve(title: &str, matches: Vec<MovieId>) -> Result<Option<String>> {
+    let (current, previous): (Vec<_>, Vec<_>) =
+        matches.into_iter().partition(|movie| movie.current);
+    let candidates = if current.is_empty() {
+        previous
+    } else {
+        current
+    };
+    match &candidates[..] {
+        [] => Ok(None),
+        [movie] => Ok(movie.id.clone()),
+        several => {
+            let ids = several
+                .iter()
+                .map(|movie| movie.id.as_deref().unwrap_or("(none)"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(HttpError::conflict(format!(
+                "several movies are titled {title}, use one of the ids {ids}"
+            ))
+            .into())
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MovieId {
+    id: Option<String>,
+    #[serde(default)]
+    current: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirects_to_the_canonical_path() {
+        let response = moved_permanently(&movie_path("3f2a9c01b7de"));
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[LOCATION], "/movie/id/3f2a9c01b7de");
+    }
+
+    fn matched(id: &str, current: bool) -> MovieId {
+        MovieId {
+            id: Some(id.to_owned()),
+            current,
+        }
+    }
+
+    #[test]
+    fn prefers_current_titles_over_previous_ones() {
+        let id = resolve("Seven", vec![matched("a", true), matched("b", false)]).unwrap();
+        assert_eq!(id.as_deref(), Some("a"));
+
+        let id = resolve("Seven", vec![matched("b", false)]).unwrap();
+        assert_eq!(id.as_deref(), Some("b"));
+
+        assert_eq!(resolve("Seven", vec![]).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_ambiguous_titles() {
+        for matches in [
+            vec![matched("a", true), matched("b", true)],
+            vec![matched("a", false), matched("b", false)],
+        ] {
+            let err = resolve("Seven", matches).unwrap_err();
+            let err = err.downcast_ref::<HttpError>().unwrap();
+            assert_eq!(err.status, StatusCode::CONFLICT);
+            assert!(err.message.contains("a, b"), "{}", err.message);
+        }
+    }
+
+    #[test]
+    fn addresses_movies_without_an_id_by_title() {
+        let mut movie = Movie {
+            title: Some("The Matrix".to_owned()),
+            ..Movie::default()
+        };
+        assert_eq!(
+            MovieKey::of(&movie),
+            Some(MovieKey::Title("The Matrix".to_owned()))
+        );
+
+        movie.id = Some("3f2a9c01b7de".to_owned());
+        assert_eq!(
+            MovieKey::of(&movie),
+            Some(MovieKey::Id("3f2a9c01b7de".to_owned()))
+        );
+    }
+}
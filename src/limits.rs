@@ -0,0 +1,213 @@
+use std::marker::PhantomData;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use color_eyre::eyre::{bail, Result, WrapErr as _};
+use serde::Deserialize;
+
+use crate::{AppError, HttpError, Service};
+
+/// The default and the largest `limit` a client may ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    pub default: i64,
+    pub max: i64,
+}
+
+impl Limit {
+    const fn new(default: i64, max: i64) -> Self {
+        Self { default, max }
+    }
+
+    /// Reads `<PREFIX>_DEFAULT_LIMIT` and `<PREFIX>_MAX_LIMIT`, falling back
+    /// to `self` for unset variables.
+    fn configured(self, prefix: &str) -> Result<Self> {
+        let var = |name: &str, fallback: i64| -> Result<i64> {
+            let var = format!("{prefix}_{name}");
+            match std::env::var(&var) {
+                Ok(value) if !value.is_empty() => value
+                    .parse()
+                    .wrap_err_with(|| format!("{var} must be an integer")),
+                _ => Ok(fallback),
+            }
+        };
+        let limit = Self {
+            default: var("DEFAULT_LIMIT", self.default)?,
+            max: var("MAX_LIMIT", self.max)?,
+        };
+
+        if !(1..=limit.max).contains(&limit.default) {
+            bail!(
+                "{prefix}_DEFAULT_LIMIT must be between 1 and {prefix}_MAX_LIMIT ({})",
+                limit.max
+            );
+        }
+        Ok(limit)
+    }
+
+    /// The requested limit, or the default. Fails with 400 if it is out of
+    /// bounds.
+    pub fn resolve(&self, requested: Option<i64>) -> Result<i64> {
+        let limit = requested.unwrap_or(self.default);
+        if !(1..=self.max).contains(&limit) {
+            return Err(HttpError::bad_request(format!(
+                "limit must be between 1 and {}",
+                self.max
+            ))
+            .into());
+        }
+        Ok(limit)
+    }
+}
+
+/// The limits of every endpoint that returns a variable number of results.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub graph: Limit,
+    pub search: Limit,
+    pub released: Limit,
+    pub near: Limit,
+    pub semantic: Limit,
+    pub export: Limit,
+    pub admin: Limit,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            graph: Limit::new(100, 1000),
+            search: Limit::new(50, 500),
+            released: Limit::new(50, 500),
+            near: Limit::new(50, 500),
+            semantic: Limit::new(10, 50),
+            export: Limit::new(10_000, 10_000),
+            admin: Limit::new(25, 100),
+        }
+    }
+}
+
+impl Limits {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        Ok(Self {
+            graph: defaults.graph.configured("GRAPH")?,
+            search: defaults.search.configured("SEARCH")?,
+            released: defaults.released.configured("RELEASED")?,
+            near: defaults.near.configured("NEAR")?,
+            semantic: defaults.semantic.configured("SEMANTIC")?,
+            export: defaults.export.configured("EXPORT")?,
+            admin: defaults.admin.configured("ADMIN")?,
+        })
+    }
+}
+
+/// An endpoint whose `limit` parameter is bounded by one of the [`Limits`].
+pub trait Endpoint {
+    fn limit(limits: &Limits) -> Limit;
+}
+
+pub enum Graph {}
+pub enum Search {}
+pub enum Released {}
+pub enum Near {}
+pub enum Semantic {}
+pub enum Export {}
+pub enum Admin {}
+
+impl Endpoint for Graph {
+    fn limit(limits: &Limits) -> Limit {
+        limits.graph
+    }
+}
+
+impl Endpoint for Search {
+    fn limit(limits: &Limits) -> Limit {
+        limits.search
+    }
+}
+
+impl Endpoint for Released {
+    fn limit(limits: &Limits) -> Limit {
+        limits.released
+    }
+}
+
+impl Endpoint for Near {
+    fn limit(limits: &Limits) -> Limit {
+        limits.near
+    }
+}
+
+impl Endpoint for Semantic {
+    fn limit(limits: &Limits) -> Limit {
+        limits.semantic
+    }
+}
+
+impl Endpoint for Export {
+    fn limit(limits: &Limits) -> Limit {
+        limits.export
+    }
+}
+
+impl Endpoint for Admin {
+    fn limit(limits: &Limits) -> Limit {
+        limits.admin
+    }
+}
+
+/// The `limit` query parameter of a request to `E`, checked against its
+/// configured maximum.
+pub struct Limited<E>(pub i64, pub PhantomData<E>);
+
+#[derive(Debug, Deserialize)]
+struct LimitParam {
+    limit: Option<i64>,
+}
+
+#[async_trait]
+impl<E: Endpoint> FromRequestParts<Service> for Limited<E> {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, service: &Service) -> Result<Self, AppError> {
+        let Query(param) = Query::<LimitParam>::from_request_parts(parts, service)
+            .await
+            .map_err(|_| HttpError::bad_request("limit must be an integer"))?;
+        let limit = E::limit(&service.limits).resolve(param.limit)?;
+        Ok(Self(limit, PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_defaults_and_rejects_out_of_bounds() {
+        let limit = Limit::new(10, 50);
+        assert_eq!(limit.resolve(None).unwrap(), 10);
+        assert_eq!(limit.resolve(Some(50)).unwrap(), 50);
+        for requested in [0, -1, 51] {
+            assert!(limit.resolve(Some(requested)).is_err(), "{requested}");
+        }
+    }
+
+    #[test]
+    fn defaults_are_within_their_maximums() {
+        let limits = Limits::default();
+        for limit in [
+            limits.graph,
+            limits.search,
+            limits.released,
+            limits.near,
+            limits.semantic,
+            limits.export,
+            limits.admin,
+        ] {
+            assert!(limit.resolve(None).is_ok(), "{limit:?}");
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::{sync::Arc, time::Duration};
+
+use color_eyre::eyre::{eyre, Result, WrapErr as _};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// A Cypher query proposed by a language model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedQuery {
+    pub cypher: String,
+    #[serde(default)]
+    pub params: Map<String, Value>,
+}
+
+/// Turns natural-language questions into parameterized Cypher.
+pub trait CypherGenerator: Send + Sync {
+    fn generate<'a>(&'a self, question: &'a str) -> BoxFuture<'a, Result<GeneratedQuery>>;
+}
+
+/// Turns texts into embedding vectors of a fixed size.
+pub trait Embedder: Send + Sync {
+    fn dimensions(&self) -> usize;
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>>;
+}
+
+/// The language model services configured through the environment.
+#[derive(Clone, Default)]
+pub struct Providers {
+    pub generator: Option<Arc<dyn CypherGenerator>>,
+    pub embedder: Option<Arc<dyn Embedder>>,
+}
+
+/// Builds the providers configured through the environment, if any.
+///
+/// `LLM_PROVIDER=openai` talks to the OpenAI chat completions and
+/// embeddings APIs, or any compatible server set through `LLM_BASE_URL`.
+pub fn providers() -> Result<Providers> {
+    let provider = std::env::var("LLM_PROVIDER").ok().filter(|s| !s.is_empty());
+
+    match provider.as_deref() {
+        None => Ok(Providers::default()),
+        Some("openai") => {
+            let openai = Arc::new(OpenAi::from_env()?);
+            Ok(Providers {
+                generator: Some(openai.clone()),
+                embedder: Some(openai),
+            })
+        }
+        Some(other) => Err(eyre!("unsupported LLM_PROVIDER {other}")),
+    }
+}
+
+/// How long a request to the language model may take, including reading
+/// the answer. Chat completions are the slowest.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long connecting to the language model may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SYSTEM_PROMPT: &str = "\
+You translate questions about a movie database into a single read-only Cypher query for Neo4j.
+
+Graph schema:
+(:Person {name, born})-[:ACTED_IN {roles}]->(:Movie {title, released, tagline, votes})
+(:Person)-[:DIRECTED|PRODUCED|WROTE|REVIEWED]->(:Movie)
+(:Person)-[:FOLLOWS]->(:Person)
+(:Movie)-[:FILMED_IN]->(:Location {name, point})
+
+Rules:
+- Only use MATCH, OPTIONAL MATCH, WITH, WHERE, UNWIND, RETURN, ORDER BY, SKIP and LIMIT.
+- Never write to the database and never call procedures.
+- Put every literal value from the question into a parameter.
+- Return named columns with simple values, not whole nodes.
+
+Answer with a JSON object: {\"cypher\": \"...\", \"params\": {...}}";
+
+struct OpenAi {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    embedding_model: String,
+    dimensions: usize,
+}
+
+impl OpenAi {
+    fn from_env() -> Result<Self> {
+        const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+        const DEFAULT_MODEL: &str = "gpt-4o-mini";
+        const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+        const DEFAULT_DIMENSIONS: usize = 1536;
+
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .connect_timeout(CONNECT_TIMEOUT)
+                .build()?,
+            base_url: std::env::var("LLM_BASE_URL")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| DEFAULT_BASE_URL.to_owned()),
+            api_key: std::env::var("LLM_API_KEY")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| eyre!("LLM_API_KEY is required for LLM_PROVIDER=openai"))?,
+            model: std::env::var("LLM_MODEL")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| DEFAULT_MODEL.to_owned()),
+            embedding_model: std::env::var("EMBEDDING_MODEL")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| DEFAULT_EMBEDDING_MODEL.to_owned()),
+            dimensions: std::env::var("EMBEDDING_DIMENSIONS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(DEFAULT_DIMENSIONS),
+        })
+    }
+
+    async fn chat(&self, question: &str) -> Result<GeneratedQuery> {
+        let request = json!({
+            "model": self.model,
+            "temperature": 0,
+            "response_format": { "type": "json_object" },
+            "messages": [
+                { "role": "system", "content": SYSTEM_PROMPT },
+                { "role": "user", "content": question },
+            ],
+        });
+
+        let response: ChatResponse = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let content = response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .ok_or_else(|| eyre!("the language model returned no answer"))?;
+
+        serde_json::from_str(&content)
+            .wrap_err_with(|| format!("the language model returned an invalid query: {content}"))
+    }
+
+    async fn embeddings(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = json!({
+            "model": self.embedding_model,
+            "input": texts,
+            "dimensions": self.dimensions,
+        });
+
+        let response: EmbeddingResponse = self
+            .client
+            .post(format!("{}/embeddings", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut data = response.data;
+        data.sort_by_key(|embedding| embedding.index);
+        if data.len() != texts.len() {
+            return Err(eyre!(
+                "asked for {} embeddings but got {}",
+                texts.len(),
+                data.len()
+            ));
+        }
+
+        Ok(data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect())
+    }
+}
+
+impl Embedder for OpenAi {
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        Box::pin(self.embeddings(texts))
+    }
+}
+
+impl CypherGenerator for OpenAi {
+    fn generate<'a>(&'a self, question: &'a str) -> BoxFuture<'a, Result<GeneratedQuery>> {
+        Box::pin(self.chat(question))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<Embedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Embedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
@@ -1,20 +1,72 @@
-use std::{collections::HashMap, net::SocketAddr};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
 
+use auth::Admin;
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
+    middleware::from_extractor_with_state,
     response::{IntoResponse, Redirect, Response},
-    routing::{get, post},
+    routing::{get, post, put},
     serve, Json, Router,
 };
-use color_eyre::eyre::{Report, Result};
-use futures::TryStreamExt as _;
-use neo4rs::{ConfigBuilder, Graph};
-use serde::{Deserialize, Serialize};
+use cache::{Cache, Lookup};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use color_eyre::eyre::{eyre, Report, Result};
+use concurrency::{Preconditions, Version};
+use db::Db;
+use degraded::Served;
+use dry_run::{DryRun, Written};
+use futures::{future::BoxFuture, StreamExt as _, TryStreamExt as _};
+use ids::MovieKey;
+use limits::{Limited, Limits};
+use llm::{CypherGenerator, Embedder};
+use neo4rs::{BoltMap, BoltType};
+use queries::Access;
+use rows::Rows;
+use serde::{Deserialize, Deserializer, Serialize};
+use singleflight::Flights;
 use tower_http::{services::ServeDir, trace::TraceLayer};
-use tracing::{debug, instrument};
+use tracing::{debug, info, instrument, warn};
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt as _, EnvFilter};
+use tx::{Tx, Unreachable};
+use warming::Views;
+
+mod admin;
+mod ask;
+mod atom;
+mod auth;
+mod cache;
+mod columnar;
+mod concurrency;
+mod db;
+mod degraded;
+mod dry_run;
+mod export;
+mod feed;
+mod hybrid;
+mod ids;
+mod lenient;
+mod limits;
+mod llm;
+mod patch;
+mod queries;
+mod rows;
+mod schema;
+mod semantic;
+mod singleflight;
+mod sitemap;
+mod temporal;
+mod transactions;
+mod tx;
+mod warming;
+mod xml;
+
+const MAX_BATCH_VOTES: usize = 100;
+/// How long expired movies and graphs are still served while they are
+/// refreshed in the background, unless configured otherwise.
+const DEFAULT_MOVIE_STALE: Duration = Duration::from_secs(60);
+const DEFAULT_GRAPH_STALE: Duration = Duration::from_secs(10 * 60);
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -26,17 +78,83 @@ async fn main() -> Result<()> {
         .with(ErrorLayer::default())
         .init();
 
-    let db = db().await?;
-    let service = Service { db };
+    queries::validate()?;
+
+    let db = Db::connect().await?;
+    let providers = llm::providers()?;
+    let service = Service {
+        db,
+        generator: providers.generator,
+        embedder: providers.embedder,
+        sitemaps: Cache::new(sitemap::CACHE_TTL),
+        limits: Limits::from_env()?,
+        movies: Cache::new(warming::CACHE_TTL)
+            .with_stale(cache::stale_window(
+                "MOVIE_CACHE_STALE_SECS",
+                DEFAULT_MOVIE_STALE,
+            ))
+            .keep_for(degraded::LAST_KNOWN_GOOD),
+        graphs: Cache::new(warming::CACHE_TTL)
+            .with_stale(cache::stale_window(
+                "GRAPH_CACHE_STALE_SECS",
+                DEFAULT_GRAPH_STALE,
+            ))
+            .keep_for(degraded::LAST_KNOWN_GOOD),
+        views: Views::default(),
+        movie_lookups: Flights::new(),
+        graph_lookups: Flights::new(),
+        ids: Cache::new(ids::ID_TTL).keep_for(degraded::LAST_KNOWN_GOOD),
+    };
+
+    let mut args = std::env::args().skip_while(|arg| arg != "--export-parquet");
+    if args.next().is_some() {
+        let dir = args
+            .next()
+            .ok_or_else(|| eyre!("--export-parquet needs a target directory"))?;
+        return service.export_parquet(dir.into()).await;
+    }
+
+    if std::env::args().any(|arg| arg == "--skip-schema") {
+        info!("skipping schema steps");
+    } else if let Err(err) = service.apply_schema().await {
+        if !schema::is_forbidden(&err) {
+            return Err(err);
+        }
+        warn!("the database does not allow schema changes, pass --skip-schema to not try: {err}");
+    }
+
+    if std::env::var("NEO4J_MIGRATE").is_ok_and(|s| s == "true" || s == "1") {
+        service.migrate().await?;
+    }
+
+    semantic::spawn_indexer(&service);
+    warming::spawn(&service);
 
     let assets_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/assets");
 
     let app = Router::new()
         .route("/", get(|| async { Redirect::temporary("/index.html") }))
-        .route("/movie/:title", get(movie))
+        .route("/movie/:title", put(update_movie))
         .route("/movie/vote/:title", post(vote))
+        .route("/movies/vote", post(vote_batch))
         .route("/search", get(search))
+        .route("/movies/released", get(released))
+        .route("/movies/near", get(near))
+        .route("/movie/:title/locations", post(add_location))
         .route("/graph", get(graph))
+        .merge(
+            admin::routes()
+                .merge(transactions::routes())
+                .route_layer(from_extractor_with_state::<Admin, _>(service.clone())),
+        )
+        .merge(ask::routes())
+        .merge(semantic::routes())
+        .merge(hybrid::routes())
+        .merge(ids::routes())
+        .merge(patch::routes())
+        .merge(sitemap::routes())
+        .merge(feed::routes())
+        .merge(export::routes())
         .fallback_service(ServeDir::new(assets_dir))
         .layer(TraceLayer::new_for_http())
         .with_state(service);
@@ -55,49 +173,20 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn db() -> Result<Graph> {
-    const DEFAULT_URL: &str = "neo4j+s://demo.neo4jlabs.com";
-    const DEFAULT_DATABASE: &str = "movies";
-    const DEFAULT_USER: &str = "movies";
-    const DEFAULT_PASS: &str = "movies";
-
-    let config = ConfigBuilder::new()
-        .uri(
-            std::env::var("NEO4J_URI")
-                .ok()
-                .filter(|s| !s.is_empty())
-                .as_deref()
-                .unwrap_or(DEFAULT_URL),
-        )
-        .user(
-            std::env::var("NEO4J_USER")
-                .ok()
-                .filter(|s| !s.is_empty())
-                .as_deref()
-                .unwrap_or(DEFAULT_USER),
-        )
-        .password(
-            std::env::var("NEO4J_PASSWORD")
-                .ok()
-                .filter(|s| !s.is_empty())
-                .as_deref()
-                .unwrap_or(DEFAULT_PASS),
-        )
-        .db(std::env::var("NEO4J_DATABASE")
-            .ok()
-            .filter(|s| !s.is_empty())
-            .as_deref()
-            .unwrap_or(DEFAULT_DATABASE))
-        .build()?;
-
-    Ok(Graph::connect(config).await?)
-}
-
-async fn movie(
+async fn update_movie(
     Path(title): Path<String>,
     State(service): State<Service>,
-) -> Result<Json<Movie>, AppError> {
-    Ok(Json(service.movie(title).await?))
+    dry_run: DryRun,
+    headers: HeaderMap,
+    Json(update): Json<MovieUpdate>,
+) -> Result<Response, AppError> {
+    let preconditions = Preconditions::from_headers(&headers)?;
+    concurrency::require(update.version, &preconditions)?;
+
+    let written = service
+        .update_movie(title, preconditions, update, dry_run)
+        .await?;
+    Ok(written.respond(|movie| (movie.current_version().headers(), Json(movie))))
 }
 
 async fn vote(
@@ -107,50 +196,199 @@ async fn vote(
     Ok(Json(service.vote(title).await?))
 }
 
+async fn vote_batch(
+    State(service): State<Service>,
+    Json(batch): Json<VoteBatch>,
+) -> Result<Json<Vec<BatchVote>>, AppError> {
+    Ok(Json(service.vote_batch(batch).await?))
+}
+
 async fn search(
     Query(search): Query<Search>,
+    Limited(limit, _): Limited<limits::Search>,
+    State(service): State<Service>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let movies = service.search(search, limit).await?;
+    Ok(columnar::respond(
+        &headers,
+        movies,
+        columnar::movie_results,
+    )?)
+}
+
+async fn released(
+    Query(range): Query<ReleasedRange>,
+    Limited(limit, _): Limited<limits::Released>,
+    State(service): State<Service>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let movies = service.released(range, limit).await?;
+    Ok(columnar::respond(
+        &headers,
+        movies,
+        columnar::movie_results,
+    )?)
+}
+
+async fn near(
+    Query(near): Query<Near>,
+    Limited(limit, _): Limited<limits::Near>,
+    State(service): State<Service>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let movies = service.near(near, limit).await?;
+    Ok(columnar::respond(
+        &headers,
+        movies,
+        columnar::nearby_movies,
+    )?)
+}
+
+async fn add_location(
+    Path(title): Path<String>,
     State(service): State<Service>,
-) -> Result<Json<Vec<MovieResult>>, AppError> {
-    Ok(Json(service.search(search).await?))
+    dry_run: DryRun,
+    Json(location): Json<NewLocation>,
+) -> Result<Response, AppError> {
+    let written = service.add_location(title, location, dry_run).await?;
+    Ok(written.respond(Json))
 }
 
 async fn graph(
-    Query(browse): Query<Browse>,
+    Limited(limit, _): Limited<limits::Graph>,
     State(service): State<Service>,
-) -> Result<Json<BrowseResponse>, AppError> {
-    Ok(Json(service.graph(browse).await?))
+) -> Result<(HeaderMap, Json<Served<BrowseResponse>>), AppError> {
+    let graph = service.graph(limit).await?;
+    Ok((graph.headers(), Json(graph)))
 }
 
 #[derive(Clone)]
 struct Service {
-    db: Graph,
+    db: Db,
+    generator: Option<Arc<dyn CypherGenerator>>,
+    embedder: Option<Arc<dyn Embedder>>,
+    sitemaps: sitemap::Sitemaps,
+    limits: Limits,
+    movies: Cache<MovieKey, Movie>,
+    /// Movie ids by title.
+    ids: Cache<String, String>,
+    graphs: Cache<i64, BrowseResponse>,
+    views: Views,
+    /// Lookups that missed the cache and are reading from the graph.
+    movie_lookups: Flights<MovieKey, Movie>,
+    graph_lookups: Flights<i64, BrowseResponse>,
 }
 
 impl Service {
-    #[instrument(skip(self))]
-    async fn movie(&self, title: String) -> Result<Movie> {
-        const FIND_MOVIE: &str = "
-            MATCH (movie:Movie {title:$title})
-            OPTIONAL MATCH (movie)<-[r]-(person:Person)
-            WITH movie.title AS title,
-            collect({
-                name:person.name,
-                job: head(split(toLower(type(r)),'_')),
-                role: r.roles
-            }) AS cast
-            LIMIT 1
-            RETURN title, cast";
-
-        let mut rows = self
+    #[instrument(skip(self, params), fields(access))]
+    async fn execute(
+        &self,
+        name: &str,
+        params: impl IntoIterator<Item = (&'static str, BoltType)>,
+    ) -> Result<Rows> {
+        let query = queries::get(name)?;
+        tracing::Span::current().record("access", tracing::field::debug(query.access));
+
+        let q = query.prepare(params)?;
+        let rows = self
             .db
-            .execute(neo4rs::query(FIND_MOVIE).param("title", title))
+            .with_graph(|graph| {
+                let q = q.clone();
+                async move {
+                    let rows = tokio::time::timeout(query.timeout, graph.execute(q))
+                        .await
+                        .map_err(|_| {
+                            Unreachable(format!("query {name} timed out after {:?}", query.timeout))
+                        })??;
+                    Ok(rows)
+                }
+            })
+            .await?;
+
+        Ok(rows.into_stream().into_stream().boxed())
+    }
+
+    async fn execute_read<T, F>(&self, work: F) -> Result<T>
+    where
+        F: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<T>>,
+    {
+        self.db
+            .with_graph(|graph| {
+                let work = &work;
+                async move { tx::run(&graph, Access::Read, work).await }
+            })
+            .await
+    }
+
+    async fn execute_write<T, F>(&self, work: F) -> Result<T>
+    where
+        F: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<T>>,
+    {
+        self.db
+            .with_graph(|graph| {
+                let work = &work;
+                async move { tx::run(&graph, Access::Write, work).await }
+            })
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn movie(&self, key: MovieKey) -> Result<Served<Movie>> {
+        let movie = match self.movies.lookup(&key) {
+            Lookup::Fresh(movie) => Served::current(movie),
+            Lookup::Stale(movie) => {
+                let service = self.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = service.refresh_movie(key).await {
+                        warn!("could not refresh the cached movie: {err:?}");
+                    }
+                });
+                Served::current(movie)
+            }
+            Lookup::LastKnownGood(movie) => {
+                degraded::refresh_or(movie, self.refresh_movie(key)).await?
+            }
+            Lookup::Missing => Served::current(self.refresh_movie(key).await?),
+        };
+
+        if let Some(key) = MovieKey::of(&movie.value) {
+            self.views.record(key);
+        }
+        Ok(movie)
+    }
+
+    /// Reads a movie into the cache, joining a read of the same movie that
+    /// is already running.
+    async fn refresh_movie(&self, key: MovieKey) -> Result<Movie> {
+        let service = self.clone();
+        let loaded = key.clone();
+        let lookup = async move {
+            let movie = service.load_movie(loaded.clone()).await?;
+            if movie.title.is_some() {
+                service.movies.insert(loaded, movie.clone());
+            }
+            Ok(movie)
+        };
+        self.movie_lookups.run(key, lookup).await
+    }
+
+    /// Reads a movie from the graph, bypassing the cache.
+    async fn load_movie(&self, key: MovieKey) -> Result<Movie> {
+        let (query, param) = match key {
+            MovieKey::Id(id) => (queries::FIND_MOVIE_BY_ID.name, ("id", id)),
+            MovieKey::Title(title) => (queries::FIND_MOVIE.name, ("title", title)),
+        };
+        let rows = self
+            .execute_read(|tx| {
+                let (name, value) = param.clone();
+                Box::pin(async move { tx.execute(query, [(name, value.into())]).await })
+            })
             .await?;
 
-        // TODO: next_as::<Movie>()?
         let movie = rows
-            .next()
-            .await?
-            .map(|r| r.to::<Movie>())
+            .first()
+            .map(rows::from_row::<Movie>)
             .transpose()?
             .unwrap_or_default();
 
@@ -163,36 +401,131 @@ impl Service {
         Ok(movie)
     }
 
+    /// Replaces the editable properties of a movie, if it is still at the
+    /// version the client expects.
     #[instrument(skip(self))]
-    async fn vote(&self, title: String) -> Result<Voted> {
-        const VOTE_IN_MOVIE: &str = "
-            MATCH (movie:Movie {title:$title})
-            SET movie.votes = coalesce(movie.votes, 0) + 1
-            RETURN movie.votes";
+    async fn update_movie(
+        &self,
+        title: String,
+        preconditions: Preconditions,
+        update: MovieUpdate,
+        dry_run: DryRun,
+    ) -> Result<Written<Movie>> {
+        let movie = self
+            .execute_mutation(dry_run, |tx| {
+                let title = title.clone();
+                let update = update.clone();
+                let preconditions = preconditions.clone();
+                Box::pin(async move {
+                    lock_movie(tx, &title, update.version, &preconditions).await?;
 
-        self.db
-            .run(neo4rs::query(VOTE_IN_MOVIE).param("title", title))
+                    let rows = tx
+                        .execute(
+                            queries::UPDATE_MOVIE.name,
+                            [
+                                ("title", title.into()),
+                                ("tagline", update.tagline.into()),
+                                ("released", update.released.into()),
+                            ],
+                        )
+                        .await?;
+                    Ok(rows::from_row::<MovieResult>(&rows[0])?.movie)
+                })
+            })
+            .await;
+
+        if let Ok(Written::Committed(_)) = &movie {
+            self.forget_movie(&title);
+        }
+        movie
+    }
+
+    /// Evicts the cached movies titled `title`, whatever they were looked
+    /// up by.
+    fn forget_movie(&self, title: &str) {
+        self.movies
+            .retain(|_, movie| movie.title.as_deref() != Some(title));
+    }
+
+    #[instrument(skip(self))]
+    async fn vote(&self, title: String) -> Result<Voted> {
+        let rows = self
+            .execute_write(|tx| {
+                let title = title.clone();
+                Box::pin(async move {
+                    tx.execute(queries::VOTE_IN_MOVIE.name, [("title", title.into())])
+                        .await
+                })
+            })
             .await?;
+        self.forget_movie(&title);
 
         // TODO:
         // let summary = self.db.run(...).await?;
 
-        Ok(Voted { updates: 1 })
+        Ok(Voted {
+            updates: rows.len(),
+        })
     }
 
+    /// Applies all votes in one transaction. Titles that are listed several
+    /// times get one vote per occurrence.
     #[instrument(skip(self))]
-    async fn search(&self, search: Search) -> Result<Vec<MovieResult>> {
-        const SEARCH_MOVIES: &str = "
-          MATCH (movie:Movie)
-          WHERE toLower(movie.title) CONTAINS toLower($part)
-          RETURN movie";
+    async fn vote_batch(&self, VoteBatch { titles }: VoteBatch) -> Result<Vec<BatchVote>> {
+        if titles.is_empty() || titles.len() > MAX_BATCH_VOTES {
+            return Err(HttpError::bad_request(format!(
+                "between 1 and {MAX_BATCH_VOTES} titles can be voted for at once"
+            ))
+            .into());
+        }
+
+        let mut counts: Vec<(String, i64)> = Vec::new();
+        for title in titles {
+            match counts.iter_mut().find(|(t, _)| *t == title) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((title, 1)),
+            }
+        }
+        let votes = counts
+            .into_iter()
+            .map(|(title, count)| {
+                let mut vote = BoltMap::default();
+                vote.put("title".into(), title.into());
+                vote.put("count".into(), count.into());
+                BoltType::Map(vote)
+            })
+            .collect::<Vec<_>>();
 
         let rows = self
-            .db
-            .execute(neo4rs::query(SEARCH_MOVIES).param("part", search.q))
+            .execute_write(|tx| {
+                let votes = votes.clone();
+                Box::pin(async move {
+                    tx.execute(queries::VOTE_IN_MOVIES.name, [("votes", votes.into())])
+                        .await
+                })
+            })
             .await?;
 
-        let movies = rows.into_stream_as::<MovieResult>().try_collect().await?;
+        let results = rows
+            .iter()
+            .map(rows::from_row::<BatchVote>)
+            .collect::<Result<Vec<_>>>()?;
+        for result in &results {
+            self.forget_movie(&result.title);
+        }
+        Ok(results)
+    }
+
+    #[instrument(skip(self))]
+    async fn search(&self, search: Search, limit: i64) -> Result<Vec<MovieResult>> {
+        let rows = self
+            .execute(
+                queries::SEARCH_MOVIES.name,
+                [("part", search.q.into()), ("limit", limit.into())],
+            )
+            .await?;
+
+        let movies = rows::collect::<MovieResult>(rows).await?;
 
         debug!(?movies);
 
@@ -200,26 +533,167 @@ impl Service {
     }
 
     #[instrument(skip(self))]
-    async fn graph(&self, browse: Browse) -> Result<BrowseResponse> {
-        const GRAPH: &str = "
-            MATCH (m:Movie)<-[:ACTED_IN]-(a:Person)
-            RETURN m.title as movie, collect(a.name) as cast
-            LIMIT $limit";
+    async fn released(&self, range: ReleasedRange, limit: i64) -> Result<Vec<MovieResult>> {
+        let rows = self
+            .execute(
+                queries::RELEASED_BETWEEN.name,
+                [
+                    ("from", range.from.into()),
+                    ("to", range.to.into()),
+                    ("limit", limit.into()),
+                ],
+            )
+            .await?;
 
-        let limit = browse.limit.unwrap_or(100);
+        let movies = rows::collect::<MovieResult>(rows).await?;
 
-        let mut rows = self
-            .db
-            .execute(neo4rs::query(GRAPH).param("limit", limit))
+        debug!(?movies);
+
+        Ok(movies)
+    }
+
+    #[instrument(skip(self))]
+    async fn near(&self, near: Near, limit: i64) -> Result<Vec<NearbyMovie>> {
+        check_coordinates(near.lat, near.lon)?;
+        if near.radius_km.is_nan() || near.radius_km <= 0.0 {
+            return Err(HttpError::bad_request("radius_km must be positive").into());
+        }
+
+        let rows = self
+            .execute(
+                queries::MOVIES_NEAR.name,
+                [
+                    ("lat", near.lat.into()),
+                    ("lon", near.lon.into()),
+                    ("radius", (near.radius_km * 1000.0).into()),
+                    ("limit", limit.into()),
+                ],
+            )
+            .await?;
+
+        let movies = rows::collect::<NearbyMovie>(rows).await?;
+
+        debug!(?movies);
+
+        Ok(movies)
+    }
+
+    #[instrument(skip(self))]
+    async fn add_location(
+        &self,
+        title: String,
+        location: NewLocation,
+        dry_run: DryRun,
+    ) -> Result<Written<Location>> {
+        check_coordinates(location.lat, location.lon)?;
+
+        self.execute_mutation(dry_run, |tx| {
+            let title = title.clone();
+            let location = location.clone();
+            Box::pin(async move {
+                let rows = tx
+                    .execute(
+                        queries::ADD_FILMING_LOCATION.name,
+                        [
+                            ("title", title.clone().into()),
+                            ("name", location.name.into()),
+                            ("lat", location.lat.into()),
+                            ("lon", location.lon.into()),
+                        ],
+                    )
+                    .await?;
+                let row = match &rows[..] {
+                    [] => {
+                        return Err(HttpError::not_found(format!("no movie titled {title}")).into())
+                    }
+                    [row] => row,
+                    // rolls back the location added to every one of them
+                    _ => {
+                        return Err(HttpError::conflict(format!(
+                            "several movies are titled {title}"
+                        ))
+                        .into())
+                    }
+                };
+
+                let FilmingLocation { location, matches } = rows::from_row(row)?;
+                if !matches {
+                    return Err(HttpError::conflict(format!(
+                        "the location {} is at {}, {}",
+                        location.name, location.latitude, location.longitude
+                    ))
+                    .into());
+                }
+                Ok(location)
+            })
+        })
+        .await
+    }
+
+    /// Converts `released` years stored by older imports into dates.
+    #[instrument(skip(self))]
+    async fn migrate(&self) -> Result<()> {
+        let rows = self
+            .execute_write(|tx| {
+                Box::pin(async move { tx.execute(queries::MIGRATE_RELEASED.name, []).await })
+            })
             .await?;
 
+        let migrated = rows
+            .first()
+            .map(|row| row.get::<i64>("migrated"))
+            .transpose()?
+            .unwrap_or_default();
+        info!(migrated, "converted release years to dates");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn graph(&self, limit: i64) -> Result<Served<BrowseResponse>> {
+        match self.graphs.lookup(&limit) {
+            Lookup::Fresh(graph) => Ok(Served::current(graph)),
+            Lookup::Stale(graph) => {
+                let service = self.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = service.refresh_graph(limit).await {
+                        warn!("could not refresh the cached graph: {err:?}");
+                    }
+                });
+                Ok(Served::current(graph))
+            }
+            Lookup::LastKnownGood(graph) => {
+                degraded::refresh_or(graph, self.refresh_graph(limit)).await
+            }
+            Lookup::Missing => Ok(Served::current(self.refresh_graph(limit).await?)),
+        }
+    }
+
+    /// Reads the graph into the cache, joining a read with the same limit
+    /// that is already running.
+    async fn refresh_graph(&self, limit: i64) -> Result<BrowseResponse> {
+        let service = self.clone();
+        let lookup = async move {
+            let graph = service.load_graph(limit).await?;
+            service.graphs.insert(limit, graph.clone());
+            Ok(graph)
+        };
+        self.graph_lookups.run(limit, lookup).await
+    }
+
+    /// Reads the graph from the database, bypassing the cache.
+    async fn load_graph(&self, limit: i64) -> Result<BrowseResponse> {
+        let rows = self
+            .execute(queries::GRAPH.name, [("limit", limit.into())])
+            .await?;
+        let mut rows = rows::map::<GraphRow>(rows);
+
         let mut actors = HashMap::<String, usize>::new();
 
         let mut nodes = Vec::new();
         let mut links = Vec::new();
 
-        while let Some(row) = rows.next().await? {
-            let movie = row.get::<String>("movie")?;
+        while let Some(GraphRow { movie, cast }) = rows.try_next().await? {
             let target = nodes.len();
 
             nodes.push(Node {
@@ -227,16 +701,15 @@ impl Service {
                 label: "movie",
             });
 
-            let cast = row.get::<Vec<&str>>("cast")?;
             for actor in cast {
-                let source = match actors.get(actor) {
+                let source = match actors.get(&actor) {
                     Some(&source) => source,
                     None => {
                         let source = nodes.len();
-                        actors.insert(actor.to_owned(), source);
+                        actors.insert(actor.clone(), source);
 
                         nodes.push(Node {
-                            title: actor.to_owned(),
+                            title: actor,
                             label: "actor",
                         });
                         source
@@ -257,36 +730,137 @@ struct Search {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct Browse {
-    limit: Option<i32>,
+struct ReleasedRange {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Near {
+    lat: f64,
+    lon: f64,
+    radius_km: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NewLocation {
+    name: String,
+    lat: f64,
+    lon: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct Movie {
-    released: Option<u32>,
+    #[serde(default, with = "temporal::release_year")]
+    released: Option<NaiveDate>,
+    /// The stable id of the movie in URLs, unlike the title.
+    id: Option<String>,
     title: Option<String>,
+    /// Alternate titles the movie is also known as, like "Seven" for "Se7en".
+    #[serde(default, deserialize_with = "lenient::null_as_default")]
+    akas: Vec<String>,
     tagline: Option<String>,
+    #[serde(default, deserialize_with = "lenient::count")]
     votes: Option<usize>,
+    #[serde(rename = "lastVotedAt")]
+    last_voted_at: Option<DateTime<FixedOffset>>,
+    #[serde(default, deserialize_with = "cast")]
     cast: Option<Vec<Person>>,
+    #[serde(default, deserialize_with = "lenient::null_as_default")]
+    version: i64,
+    #[serde(rename = "updatedAt")]
+    updated_at: Option<DateTime<FixedOffset>>,
+}
+
+impl Movie {
+    fn current_version(&self) -> Version {
+        Version {
+            version: self.version,
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+/// The editable properties of a movie, together with the version they
+/// replace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MovieUpdate {
+    tagline: Option<String>,
+    #[serde(default, with = "temporal::release_year")]
+    released: Option<NaiveDate>,
+    version: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GraphRow {
+    movie: String,
+    cast: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct MovieResult {
     movie: Movie,
+    /// The alternate title a search matched, if it did not match the title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matched_aka: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NearbyMovie {
+    movie: Movie,
+    location: Location,
+    distance_km: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FilmingLocation {
+    location: Location,
+    /// Whether the location is at the requested coordinates. Locations are
+    /// shared between movies, so they are never moved.
+    matches: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Location {
+    name: String,
+    latitude: f64,
+    longitude: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Person {
+    #[serde(default, deserialize_with = "lenient::null_as_default")]
     job: String,
     role: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "lenient::null_as_default")]
     name: String,
 }
 
+/// Drops the all-null entry that `OPTIONAL MATCH` and `collect` produce for
+/// a movie without any people.
+fn cast<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<Person>>, D::Error> {
+    let cast = Option::<Vec<Person>>::deserialize(d)?;
+    Ok(cast.map(|cast| cast.into_iter().filter(|p| !p.name.is_empty()).collect()))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Voted {
     updates: usize,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VoteBatch {
+    titles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BatchVote {
+    title: String,
+    found: bool,
+    #[serde(default, deserialize_with = "lenient::count")]
+    votes: Option<usize>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(bound(deserialize = "'de: 'static"))]
 struct BrowseResponse {
@@ -306,10 +880,54 @@ struct Link {
     target: usize,
 }
 
+/// Locks a movie for the rest of the transaction and checks that it is
+/// still in the state the client based its change on. A title shared by
+/// several movies is a conflict, since the change would apply to all.
+async fn lock_movie(
+    tx: &mut Tx,
+    title: &str,
+    expected: Option<i64>,
+    preconditions: &Preconditions,
+) -> Result<()> {
+    let rows = tx
+        .execute(queries::LOCK_MOVIE.name, [("title", title.into())])
+        .await?;
+    let row = match &rows[..] {
+        [] => return Err(HttpError::not_found(format!("no movie titled {title}")).into()),
+        [row] => row,
+        _ => {
+            return Err(HttpError::conflict(format!(
+                "several movies are titled {title}, edit them through the admin endpoints"
+            ))
+            .into())
+        }
+    };
+
+    let current = rows::from_row::<Version>(row)?;
+    if let Some(expected) = expected {
+        concurrency::check(current.version, expected)?;
+    }
+    preconditions.evaluate(&current)
+}
+
+fn check_coordinates(lat: f64, lon: f64) -> Result<()> {
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(HttpError::bad_request("lat must be within ±90 and lon within ±180").into());
+    }
+    Ok(())
+}
+
 struct AppError(Report);
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let Some(err) = self.0.downcast_ref::<HttpError>() {
+            return (err.status, err.message.clone()).into_response();
+        }
+        if let Some(err) = self.0.downcast_ref::<Unreachable>() {
+            return (StatusCode::SERVICE_UNAVAILABLE, err.to_string()).into_response();
+        }
+
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Something went wrong: {}", self.0),
@@ -328,3 +946,211 @@ where
         Self(err)
     }
 }
+
+/// An error that is reported to the client with a specific status code.
+#[derive(Debug, Clone)]
+struct HttpError {
+    status: StatusCode,
+    message: String,
+}
+
+impl HttpError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    fn unauthorized(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            message: message.into(),
+        }
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        }
+    }
+
+    fn conflict(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            message: message.into(),
+        }
+    }
+
+    fn precondition_required(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::PRECONDITION_REQUIRED,
+            message: message.into(),
+        }
+    }
+
+    fn precondition_failed(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::PRECONDITION_FAILED,
+            message: message.into(),
+        }
+    }
+
+    fn service_unavailable(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+#[cfg(test)]
+mod tests {
+    use neo4rs::{BoltDate, BoltList, BoltMap, BoltNode, BoltNull, BoltType, Row};
+
+    use super::*;
+
+    fn row(columns: Vec<(&str, BoltType)>) -> Row {
+        let (fields, data): (Vec<_>, Vec<_>) = columns
+            .into_iter()
+            .map(|(k, v)| (BoltType::from(k), v))
+            .unzip();
+        Row::new(BoltList::from(fields), BoltList::from(data))
+    }
+
+    fn movie_node(props: Vec<(&str, BoltType)>) -> BoltType {
+        let mut map = BoltMap::default();
+        for (k, v) in props {
+            map.put(k.into(), v);
+        }
+        let labels = BoltList::from(vec![BoltType::from("Movie")]);
+        BoltType::Node(BoltNode::new(0.into(), labels, map))
+    }
+
+    fn person(name: BoltType, job: BoltType, role: BoltType) -> BoltType {
+        let mut map = BoltMap::default();
+        map.put("name".into(), name);
+        map.put("job".into(), job);
+        map.put("role".into(), role);
+        BoltType::Map(map)
+    }
+
+    fn null() -> BoltType {
+        BoltType::Null(BoltNull)
+    }
+
+    #[test]
+    fn movie_with_only_a_title() {
+        let row = row(vec![(
+            "movie",
+            movie_node(vec![("title", "The Matrix".into())]),
+        )]);
+
+        let movie = rows::from_row::<MovieResult>(&row).unwrap().movie;
+        assert_eq!(movie.title.as_deref(), Some("The Matrix"));
+        assert_eq!(movie.released, None);
+        assert_eq!(movie.votes, None);
+        assert!(movie.cast.is_none());
+    }
+
+    #[test]
+    fn movie_with_null_properties() {
+        let row = row(vec![(
+            "movie",
+            movie_node(vec![
+                ("title", "The Matrix".into()),
+                ("released", null()),
+                ("tagline", null()),
+                ("votes", null()),
+                ("lastVotedAt", null()),
+            ]),
+        )]);
+
+        let movie = rows::from_row::<MovieResult>(&row).unwrap().movie;
+        assert_eq!(movie.released, None);
+        assert_eq!(movie.tagline, None);
+        assert_eq!(movie.votes, None);
+        assert_eq!(movie.last_voted_at, None);
+    }
+
+    #[test]
+    fn votes_stored_as_floats_or_strings() {
+        for (votes, expected) in [
+            (BoltType::from(3_i64), 3),
+            (BoltType::from(3.0_f64), 3),
+            (BoltType::from(2.6_f64), 3),
+            (BoltType::from("7"), 7),
+            (BoltType::from("7.0"), 7),
+        ] {
+            let row = row(vec![("movie", movie_node(vec![("votes", votes)]))]);
+            let movie = rows::from_row::<MovieResult>(&row).unwrap().movie;
+            assert_eq!(movie.votes, Some(expected));
+        }
+    }
+
+    #[test]
+    fn negative_votes_name_the_column() {
+        let row = row(vec![(
+            "movie",
+            movie_node(vec![("votes", (-1_i64).into())]),
+        )]);
+        let err = rows::from_row::<MovieResult>(&row).unwrap_err();
+        assert!(err.to_string().contains("`movie.votes`"), "{err}");
+    }
+
+    #[test]
+    fn released_as_year_or_date() {
+        let date = NaiveDate::from_ymd_opt(1999, 3, 31).unwrap();
+        for (released, expected) in [
+            (
+                BoltType::from(1999_i64),
+                NaiveDate::from_ymd_opt(1999, 1, 1),
+            ),
+            (BoltType::Date(BoltDate::from(date)), Some(date)),
+        ] {
+            let row = row(vec![("movie", movie_node(vec![("released", released)]))]);
+            let movie = rows::from_row::<MovieResult>(&row).unwrap().movie;
+            assert_eq!(movie.released, expected);
+        }
+    }
+
+    #[test]
+    fn cast_without_people() {
+        let cast = BoltType::List(BoltList::from(vec![person(null(), null(), null())]));
+        let row = row(vec![("title", "The Matrix".into()), ("cast", cast)]);
+
+        let movie = rows::from_row::<Movie>(&row).unwrap();
+        assert_eq!(movie.cast.unwrap().len(), 0);
+    }
+
+    #[test]
+    fn cast_with_missing_roles() {
+        let cast = BoltType::List(BoltList::from(vec![
+            person("Keanu Reeves".into(), "acted".into(), null()),
+            person("Lana Wachowski".into(), null(), null()),
+        ]));
+        let row = row(vec![("title", "The Matrix".into()), ("cast", cast)]);
+
+        let cast = rows::from_row::<Movie>(&row).unwrap().cast.unwrap();
+        assert_eq!(cast.len(), 2);
+        assert_eq!(cast[0].job, "acted");
+        assert!(cast[0].role.is_none());
+        assert_eq!(cast[1].job, "");
+    }
+}
@@ -0,0 +1,318 @@
+use std::{fmt::Write as _, time::Duration};
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    routing::patch,
+    Json, Router,
+};
+use color_eyre::eyre::Result;
+use neo4rs::BoltType;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, instrument};
+
+use crate::{
+    concurrency::{self, Preconditions},
+    dry_run::{DryRun, Written},
+    ids::MovieKey,
+    lock_movie, queries, rows, temporal,
+    tx::Tx,
+    AppError, HttpError, Movie, MovieResult, Service,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The movie properties a merge patch may change. Only these names end up
+/// in the generated Cypher, the values are always passed as parameters.
+const PATCHABLE: &[&str] = &["title", "tagline", "released", "akas"];
+
+/// The patchable properties that movie embeddings are computed from.
+const EMBEDDED: &[&str] = &["title", "tagline"];
+
+pub fn routes() -> Router<Service> {
+    Router::new().route("/movie/:title", patch(patch_movie))
+}
+
+async fn patch_movie(
+    Path(title): Path<String>,
+    State(service): State<Service>,
+    dry_run: DryRun,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> Result<Response, AppError> {
+    let preconditions = Preconditions::from_headers(&headers)?;
+    let patch = MergePatch::parse(patch)?;
+    concurrency::require(patch.version, &preconditions)?;
+
+    let written = service
+        .patch_movie(title, preconditions, patch, dry_run)
+        .await?;
+    Ok(written.respond(|movie| (movie.current_version().headers(), Json(movie))))
+}
+
+impl Service {
+    #[instrument(skip(self))]
+    async fn patch_movie(
+        &self,
+        title: String,
+        preconditions: Preconditions,
+        patch: MergePatch,
+        dry_run: DryRun,
+    ) -> Result<Written<Movie>> {
+        let cypher = patch.cypher();
+        debug!(%cypher);
+
+        let mut query = neo4rs::Query::new(cypher).param("title", title.clone());
+        for (property, value) in &patch.set {
+            query = query.param(&format!("set_{property}"), value.clone());
+        }
+        let new_title = patch.new_title();
+
+        let movie = self
+            .execute_mutation(dry_run, |tx| {
+                let title = title.clone();
+                let new_title = new_title.clone();
+                let query = query.clone();
+                let preconditions = preconditions.clone();
+                Box::pin(async move {
+                    lock_movie(tx, &title, patch.version, &preconditions).await?;
+                    if let Some(new_title) = new_title {
+                        check_title_available(tx, &title, &new_title).await?;
+                    }
+
+                    let rows = tx.execute_unregistered(query, TIMEOUT).await?;
+                    Ok(rows::from_row::<MovieResult>(&rows[0])?.movie)
+                })
+            })
+            .await;
+
+        if let Ok(Written::Committed(movie)) = &movie {
+            self.forget_movie(&title);
+            if let Some(new_title) = &new_title {
+                self.forget_movie(new_title);
+                self.ids.remove(new_title);
+            }
+            if let Some(id) = &movie.id {
+                self.movies.remove(&MovieKey::Id(id.clone()));
+            }
+        }
+        movie
+    }
+}
+
+/// Fails with 409 if another movie already has the title, since titles
+/// still identify movies in the editing and voting routes.
+async fn check_title_available(tx: &mut Tx, title: &str, new_title: &str) -> Result<()> {
+    let rows = tx
+        .execute(
+            queries::TITLE_TAKEN.name,
+            [("title", title.into()), ("new_title", new_title.into())],
+        )
+        .await?;
+    if rows::from_row::<Taken>(&rows[0])?.taken {
+        return Err(HttpError::conflict(format!("there already is a movie {new_title}")).into());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct Taken {
+    taken: bool,
+}
+
+/// An RFC 7386 merge patch of a movie. Absent members are left alone,
+/// `null` removes a property and any other value replaces it. A `version`
+/// member is not a change but the version the patch applies to.
+#[derive(Debug, Clone)]
+struct MergePatch {
+    version: Option<i64>,
+    set: Vec<(&'static str, BoltType)>,
+    remove: Vec<&'static str>,
+}
+
+impl MergePatch {
+    fn parse(patch: Value) -> Result<Self> {
+        let Value::Object(members) = patch else {
+            return Err(HttpError::bad_request("a movie patch must be a JSON object").into());
+        };
+
+        let mut patch = Self {
+            version: None,
+            set: Vec::new(),
+            remove: Vec::new(),
+        };
+        for (name, value) in members {
+            if name == "version" {
+                let version = value
+                    .as_i64()
+                    .ok_or_else(|| HttpError::bad_request("version must be an integer"))?;
+                patch.version = Some(version);
+                continue;
+            }
+
+            let Some(&property) = PATCHABLE.iter().find(|property| **property == name) else {
+                return Err(HttpError::bad_request(format!(
+                    "{name} cannot be patched, only {}",
+                    PATCHABLE.join(", ")
+                ))
+                .into());
+            };
+            match to_property(property, value)? {
+                Some(value) => patch.set.push((property, value)),
+                None if property == "title" => {
+                    return Err(HttpError::bad_request("the title cannot be removed").into())
+                }
+                None => patch.remove.push(property),
+            }
+        }
+
+        if patch.set.is_empty() && patch.remove.is_empty() {
+            return Err(HttpError::bad_request("the patch does not change anything").into());
+        }
+        Ok(patch)
+    }
+
+    /// The title the movie is renamed to, if any.
+    fn new_title(&self) -> Option<String> {
+        self.set.iter().find_map(|(property, value)| match value {
+            BoltType::String(title) if *property == "title" => Some(title.value.clone()),
+            _ => None,
+        })
+    }
+
+    /// Whether the patch changes the text that movie embeddings are computed
+    /// from.
+    fn changes_embedded_text(&self) -> bool {
+        let embedded = |property: &&str| EMBEDDED.contains(property);
+        self.set.iter().map(|(property, _)| property).any(embedded)
+            || self.remove.iter().any(embedded)
+    }
+
+    fn cypher(&self) -> String {
+        let mut cypher = String::from("MATCH (movie:Movie {title: $title})\n");
+        for (property, _) in &self.set {
+            if *property == "title" {
+                // the old title keeps redirecting to the movie
+                cypher.push_str(
+                    "SET movie.previousTitles = CASE WHEN movie.title = $set_title \
+                     THEN movie.previousTitles \
+                     ELSE [t IN coalesce(movie.previousTitles, []) WHERE t <> $set_title] + movie.title END\n",
+                );
+            }
+            let _ = writeln!(cypher, "SET movie.{property} = $set_{property}");
+        }
+        for property in &self.remove {
+            let _ = writeln!(cypher, "REMOVE movie.{property}");
+        }
+        if self.changes_embedded_text() {
+            // the indexer embeds the movie again
+            cypher.push_str("REMOVE movie.embedding\n");
+        }
+        cypher.push_str(
+            "SET movie.version = coalesce(movie.version, 0) + 1, movie.updatedAt = datetime()\n\
+             RETURN movie",
+        );
+        cypher
+    }
+}
+
+/// Converts a patched value into its stored form, or `None` for `null`.
+fn to_property(property: &str, value: Value) -> Result<Option<BoltType>> {
+    let invalid = || HttpError::bad_request(format!("invalid value for {property}"));
+
+    if value.is_null() {
+        return Ok(None);
+    }
+    let value = match property {
+        "released" => temporal::release_year::deserialize(value)
+            .map_err(|_| invalid())?
+            .map(BoltType::from),
+        "akas" => Some(
+            serde_json::from_value::<Vec<String>>(value)
+                .map_err(|_| invalid())?
+                .into(),
+        ),
+        _ => Some(value.as_str().ok_or_else(invalid)?.into()),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn translates_members_into_set_and_remove() {
+        let patch = MergePatch::parse(json!({
+            "tagline": "Free your mind",
+            "released": null,
+            "version": 3,
+        }))
+        .unwrap();
+
+        assert_eq!(patch.version, Some(3));
+        assert_eq!(
+            patch.cypher(),
+            "MATCH (movie:Movie {title: $title})\n\
+             SET movie.tagline = $set_tagline\n\
+             REMOVE movie.released\n\
+             REMOVE movie.embedding\n\
+             SET movie.version = coalesce(movie.version, 0) + 1, movie.updatedAt = datetime()\n\
+             RETURN movie"
+        );
+    }
+
+    #[test]
+    fn keeps_embeddings_unless_their_text_changes() {
+        let patch = MergePatch::parse(json!({ "released": 1999, "akas": [] })).unwrap();
+        assert!(!patch.cypher().contains("embedding"));
+
+        let patch = MergePatch::parse(json!({ "title": "Seven" })).unwrap();
+        assert!(patch.cypher().contains("REMOVE movie.embedding"));
+    }
+
+    #[test]
+    fn accepts_release_years_and_dates() {
+        for released in [json!(1999), json!("1999-03-31")] {
+            let patch = MergePatch::parse(json!({ "released": released })).unwrap();
+            assert!(matches!(patch.set[..], [("released", BoltType::Date(_))]));
+        }
+    }
+
+    #[test]
+    fn records_previous_titles() {
+        let patch = MergePatch::parse(json!({ "title": "Seven", "version": 1 })).unwrap();
+
+        assert_eq!(patch.new_title().as_deref(), Some("Seven"));
+        let cypher = patch.cypher();
+        let previous = cypher.find("SET movie.previousTitles").unwrap();
+        let title = cypher.find("SET movie.title = $set_title").unwrap();
+        assert!(previous < title, "{cypher}");
+    }
+
+    #[test]
+    fn accepts_lists_of_alternate_titles() {
+        let patch = MergePatch::parse(json!({ "akas": ["Seven"] })).unwrap();
+        assert!(matches!(patch.set[..], [("akas", BoltType::List(_))]));
+    }
+
+    #[test]
+    fn rejects_unknown_properties_and_invalid_values() {
+        for patch in [
+            json!({ "plot": "Neo wakes up" }),
+            json!({ "title": null }),
+            json!({ "`tagline` = 1 DETACH DELETE movie //": "x" }),
+            json!({ "tagline": 42 }),
+            json!({ "released": "last year" }),
+            json!({ "akas": "Seven" }),
+            json!({ "version": 1 }),
+            json!(["tagline"]),
+        ] {
+            assert!(MergePatch::parse(patch.clone()).is_err(), "{patch}");
+        }
+    }
+}
@@ -0,0 +1,802 @@
+use std::{collections::HashSet, time::Duration};
+
+use color_eyre::eyre::{bail, eyre, Result};
+use neo4rs::BoltType;
+
+/// Whether a query only reads from the graph or also writes to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Placeholder for a label that is only known at runtime. Labels cannot be
+/// passed as parameters, so it is replaced by the escaped label before the
+/// query runs.
+pub const LABEL: &str = "{label}";
+
+/// A named Cypher statement together with the metadata needed to run it.
+#[derive(Debug)]
+pub struct Query {
+    pub name: &'static str,
+    pub cypher: &'static str,
+    pub access: Access,
+    pub params: &'static [&'static str],
+    pub timeout: Duration,
+}
+
+pub const FIND_MOVIE: Query = Query {
+    name: "find_movie",
+    cypher: "
+        MATCH (movie:Movie {title:$title})
+        OPTIONAL MATCH (movie)<-[r]-(person:Person)
+        WITH movie.id AS id, movie.title AS title, movie.akas AS akas,
+        coalesce(movie.version, 0) AS version, movie.updatedAt AS updatedAt,
+        collect({
+            name:person.name,
+            job: head(split(toLower(type(r)),'_')),
+            role: r.roles
+        }) AS cast
+        LIMIT 1
+        RETURN id, title, akas, version, updatedAt, cast",
+    access: Access::Read,
+    params: &["title"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const FIND_MOVIE_BY_ID: Query = Query {
+    name: "find_movie_by_id",
+    cypher: "
+        MATCH (movie:Movie {id:$id})
+        OPTIONAL MATCH (movie)<-[r]-(person:Person)
+        WITH movie.id AS id, movie.title AS title, movie.akas AS akas,
+        coalesce(movie.version, 0) AS version, movie.updatedAt AS updatedAt,
+        collect({
+            name:person.name,
+            job: head(split(toLower(type(r)),'_')),
+            role: r.roles
+        }) AS cast
+        RETURN id, title, akas, version, updatedAt, cast",
+    access: Access::Read,
+    params: &["id"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const VOTE_IN_MOVIE: Query = Query {
+    name: "vote_in_movie",
+    cypher: "
+        MATCH (movie:Movie {title:$title})
+        SET movie.votes = coalesce(movie.votes, 0) + 1,
+            movie.lastVotedAt = datetime(),
+            movie.version = coalesce(movie.version, 0) + 1,
+            movie.updatedAt = datetime()
+        RETURN movie.votes",
+    access: Access::Write,
+    params: &["title"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const VOTE_IN_MOVIES: Query = Query {
+    name: "vote_in_movies",
+    // SET is a no-op for titles that match no movie, which are still returned
+    cypher: "
+        UNWIND $votes AS vote
+        OPTIONAL MATCH (movie:Movie {title: vote.title})
+        SET movie.votes = coalesce(movie.votes, 0) + vote.count,
+            movie.lastVotedAt = datetime(),
+            movie.version = coalesce(movie.version, 0) + 1,
+            movie.updatedAt = datetime()
+        RETURN vote.title AS title, movie IS NOT NULL AS found, movie.votes AS votes",
+    access: Access::Write,
+    params: &["votes"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const LOCK_MOVIE: Query = Query {
+    name: "lock_movie",
+    // the write lock is held until the transaction ends, so the version
+    // cannot change between checking and updating it
+    cypher: "
+        MATCH (movie:Movie {title:$title})
+        SET movie._lock = true
+        REMOVE movie._lock
+        RETURN coalesce(movie.version, 0) AS version, movie.updatedAt AS updatedAt",
+    access: Access::Write,
+    params: &["title"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const UPDATE_MOVIE: Query = Query {
+    name: "update_movie",
+    cypher: "
+        MATCH (movie:Movie {title:$title})
+        SET movie.embedding = CASE
+            WHEN coalesce(movie.tagline, '') = coalesce($tagline, '') THEN movie.embedding
+        END
+        SET movie.tagline = $tagline,
+            movie.released = $released,
+            movie.version = coalesce(movie.version, 0) + 1,
+            movie.updatedAt = datetime()
+        RETURN movie",
+    access: Access::Write,
+    params: &["title", "tagline", "released"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const SEARCH_MOVIES: Query = Query {
+    name: "search_movies",
+    cypher: "
+        MATCH (movie:Movie)
+        WITH movie,
+            toLower(movie.title) CONTAINS toLower($part) AS title_matched,
+            [aka IN coalesce(movie.akas, []) WHERE toLower(aka) CONTAINS toLower($part)] AS akas
+        WHERE title_matched OR size(akas) > 0
+        RETURN movie, CASE WHEN title_matched THEN null ELSE akas[0] END AS matched_aka
+        ORDER BY movie.title
+        LIMIT $limit",
+    access: Access::Read,
+    params: &["part", "limit"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const GRAPH: Query = Query {
+    name: "graph",
+    cypher: "
+        MATCH (m:Movie)<-[:ACTED_IN]-(a:Person)
+        RETURN m.title as movie, collect(a.name) as cast
+        LIMIT $limit",
+    access: Access::Read,
+    params: &["limit"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const RELEASED_BETWEEN: Query = Query {
+    name: "released_between",
+    cypher: "
+        MATCH (movie:Movie)
+        WITH movie, date(toString(movie.released)) AS released
+        WHERE ($from IS NULL OR released >= $from)
+          AND ($to IS NULL OR released <= $to)
+        RETURN movie
+        ORDER BY released, movie.title
+        LIMIT $limit",
+    access: Access::Read,
+    params: &["from", "to", "limit"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const MOVIES_NEAR: Query = Query {
+    name: "movies_near",
+    cypher: "
+        WITH point({latitude: $lat, longitude: $lon}) AS origin
+        MATCH (location:Location)
+        WHERE point.distance(location.point, origin) <= $radius
+        MATCH (movie:Movie)-[:FILMED_IN]->(location)
+        WITH movie, location, point.distance(location.point, origin) AS meters
+        RETURN movie,
+            location {
+                .name,
+                latitude: location.point.latitude,
+                longitude: location.point.longitude
+            } AS location,
+            meters / 1000.0 AS distance_km
+        ORDER BY distance_km, movie.title
+        LIMIT $limit",
+    access: Access::Read,
+    params: &["lat", "lon", "radius", "limit"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const ADD_FILMING_LOCATION: Query = Query {
+    name: "add_filming_location",
+    cypher: "
+        MATCH (movie:Movie {title:$title})
+        MERGE (location:Location {name:$name})
+        SET location.point = coalesce(location.point, point({latitude: $lat, longitude: $lon}))
+        MERGE (movie)-[:FILMED_IN]->(location)
+        RETURN location {
+            .name,
+            latitude: location.point.latitude,
+            longitude: location.point.longitude
+        } AS location,
+        location.point = point({latitude: $lat, longitude: $lon}) AS matches",
+    access: Access::Write,
+    params: &["title", "name", "lat", "lon"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const CREATE_VECTOR_INDEX: Query = Query {
+    name: "create_vector_index",
+    cypher: "
+        CREATE VECTOR INDEX movie_embeddings IF NOT EXISTS
+        FOR (movie:Movie) ON (movie.embedding)
+        OPTIONS {indexConfig: {
+            `vector.dimensions`: $dimensions,
+            `vector.similarity_function`: 'cosine'
+        }}",
+    access: Access::Write,
+    params: &["dimensions"],
+    timeout: Duration::from_secs(30),
+};
+
+pub const MOVIES_WITHOUT_EMBEDDING: Query = Query {
+    name: "movies_without_embedding",
+    cypher: "
+        MATCH (movie:Movie)
+        WHERE movie.embedding IS NULL AND movie.title IS NOT NULL
+          AND (movie.tagline IS NOT NULL OR movie.plot IS NOT NULL)
+        RETURN elementId(movie) AS id,
+            movie.title + ': ' + coalesce(movie.tagline, '') + ' ' + coalesce(movie.plot, '')
+                AS text
+        LIMIT $limit",
+    access: Access::Read,
+    params: &["limit"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const SET_EMBEDDINGS: Query = Query {
+    name: "set_embeddings",
+    cypher: "
+        UNWIND $embeddings AS row
+        MATCH (movie:Movie)
+        WHERE elementId(movie) = row.id
+        SET movie.embedding = row.embedding
+        RETURN count(movie) AS updated",
+    access: Access::Write,
+    params: &["embeddings"],
+    timeout: Duration::from_secs(30),
+};
+
+pub const SEMANTIC_SEARCH: Query = Query {
+    name: "semantic_search",
+    cypher: "
+        CALL db.index.vector.queryNodes('movie_embeddings', $limit, $embedding)
+        YIELD node AS movie, score
+        RETURN elementId(movie) AS id, movie, score
+        ORDER BY score DESC",
+    access: Access::Read,
+    params: &["limit", "embedding"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const CREATE_FULLTEXT_INDEX: Query = Query {
+    name: "create_fulltext_index",
+    cypher: "
+        CREATE FULLTEXT INDEX movie_fulltext IF NOT EXISTS
+        FOR (movie:Movie) ON EACH [movie.title, movie.tagline]",
+    access: Access::Write,
+    params: &[],
+    timeout: Duration::from_secs(30),
+};
+
+pub const FULLTEXT_SEARCH: Query = Query {
+    name: "fulltext_search",
+    cypher: "
+        CALL db.index.fulltext.queryNodes('movie_fulltext', $query, {limit: $limit})
+        YIELD node AS movie, score
+        RETURN elementId(movie) AS id, movie, score
+        ORDER BY score DESC",
+    access: Access::Read,
+    params: &["query", "limit"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const COUNT_MOVIES: Query = Query {
+    name: "count_movies",
+    cypher: "
+        MATCH (movie:Movie)
+        WHERE movie.title IS NOT NULL AND movie.id IS NOT NULL
+        RETURN count(movie) AS movies",
+    access: Access::Read,
+    params: &[],
+    timeout: Duration::from_secs(10),
+};
+
+pub const SITEMAP_MOVIES: Query = Query {
+    name: "sitemap_movies",
+    cypher: "
+        MATCH (movie:Movie)
+        WHERE movie.title IS NOT NULL AND movie.id IS NOT NULL
+        RETURN movie.id AS id, toString(date(movie.updatedAt)) AS lastmod
+        ORDER BY movie.id
+        SKIP $skip
+        LIMIT $limit",
+    access: Access::Read,
+    params: &["skip", "limit"],
+    timeout: Duration::from_secs(60),
+};
+
+pub const NEWEST_MOVIES: Query = Query {
+    name: "newest_movies",
+    cypher: "
+        MATCH (movie:Movie)
+        WHERE movie.title IS NOT NULL AND movie.id IS NOT NULL AND movie.createdAt IS NOT NULL
+        RETURN movie.id AS id, movie.title AS title, movie.tagline AS tagline,
+            movie.createdAt AS createdAt, movie.updatedAt AS updatedAt
+        ORDER BY movie.createdAt DESC
+        LIMIT $limit",
+    access: Access::Read,
+    params: &["limit"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const EXPORT_MOVIES: Query = Query {
+    name: "export_movies",
+    cypher: "
+        MATCH (movie:Movie)
+        RETURN elementId(movie) AS id, movie",
+    access: Access::Read,
+    params: &[],
+    timeout: Duration::from_secs(60),
+};
+
+pub const EXPORT_PEOPLE: Query = Query {
+    name: "export_people",
+    cypher: "
+        MATCH (person:Person)
+        RETURN elementId(person) AS id, person.name AS name, person.born AS born",
+    access: Access::Read,
+    params: &[],
+    timeout: Duration::from_secs(60),
+};
+
+pub const EXPORT_RELATIONSHIPS: Query = Query {
+    name: "export_relationships",
+    cypher: "
+        MATCH (source)-[r]->(target)
+        WHERE (source:Person OR source:Movie) AND (target:Person OR target:Movie)
+        RETURN elementId(source) AS source, elementId(target) AS target,
+            type(r) AS type, r.roles AS roles",
+    access: Access::Read,
+    params: &[],
+    timeout: Duration::from_secs(60),
+};
+
+pub const TOP_VOTED_MOVIES: Query = Query {
+    name: "top_voted_movies",
+    cypher: "
+        MATCH (movie:Movie)
+        WHERE movie.title IS NOT NULL AND movie.votes IS NOT NULL
+        RETURN movie.id AS id, movie.title AS title
+        ORDER BY movie.votes DESC
+        LIMIT $limit",
+    access: Access::Read,
+    params: &["limit"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const LABELS: Query = Query {
+    name: "labels",
+    cypher: "
+        CALL db.labels() YIELD label
+        RETURN collect(label) AS labels",
+    access: Access::Read,
+    params: &[],
+    timeout: Duration::from_secs(5),
+};
+
+pub const ADMIN_LIST_NODES: Query = Query {
+    name: "admin_list_nodes",
+    cypher: "
+        MATCH (n:{label})
+        RETURN elementId(n) AS id, properties(n) AS properties
+        ORDER BY id
+        SKIP $skip
+        LIMIT $limit",
+    access: Access::Read,
+    params: &["skip", "limit"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const ADMIN_GET_NODE: Query = Query {
+    name: "admin_get_node",
+    cypher: "
+        MATCH (n:{label})
+        WHERE elementId(n) = $id
+        RETURN elementId(n) AS id, properties(n) AS properties",
+    access: Access::Read,
+    params: &["id"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const ADMIN_CREATE_NODE: Query = Query {
+    name: "admin_create_node",
+    cypher: "
+        CREATE (n:{label})
+        SET n = $properties
+        SET n.createdAt = datetime()
+        FOREACH (assign IN CASE WHEN (n:Movie OR n:Person) AND n.id IS NULL THEN [1] ELSE [] END |
+            SET n.id = substring(replace(randomUUID(), '-', ''), 0, 12))
+        RETURN elementId(n) AS id, properties(n) AS properties",
+    access: Access::Write,
+    params: &["properties"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const ADMIN_UPDATE_NODE: Query = Query {
+    name: "admin_update_node",
+    // properties the application maintains are kept unless they are sent,
+    // the embedding only while the text it was computed from is unchanged,
+    // and a renamed movie keeps its old title in previousTitles
+    cypher: "
+        MATCH (n:{label})
+        WHERE elementId(n) = $id
+        WITH n, coalesce(n.version, 0) + 1 AS version, n.createdAt AS createdAt, n.id AS nodeId,
+            n.title AS title, coalesce(n.tagline, '') AS tagline, coalesce(n.plot, '') AS plot,
+            n {.previousTitles, .akas, .votes, .lastVotedAt, .embedding} AS kept
+        SET n = $properties
+        SET n.version = version, n.createdAt = createdAt, n.updatedAt = datetime(),
+            n.id = coalesce(nodeId, n.id),
+            n.akas = coalesce(n.akas, kept.akas),
+            n.votes = coalesce(n.votes, kept.votes),
+            n.lastVotedAt = coalesce(n.lastVotedAt, kept.lastVotedAt),
+            n.embedding = coalesce(n.embedding, CASE
+                WHEN n.title = title AND coalesce(n.tagline, '') = tagline
+                    AND coalesce(n.plot, '') = plot
+                THEN kept.embedding
+            END)
+        WITH n, title, coalesce(n.previousTitles, kept.previousTitles) AS previousTitles
+        SET n.previousTitles = CASE
+            WHEN n:Movie AND title <> n.title AND NOT title IN coalesce(previousTitles, [])
+            THEN coalesce(previousTitles, []) + title
+            ELSE previousTitles
+        END
+        RETURN elementId(n) AS id, properties(n) AS properties",
+    access: Access::Write,
+    params: &["id", "properties"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const ADMIN_LOCK_NODE: Query = Query {
+    name: "admin_lock_node",
+    cypher: "
+        MATCH (n:{label})
+        WHERE elementId(n) = $id
+        SET n._lock = true
+        REMOVE n._lock
+        RETURN coalesce(n.version, 0) AS version, n.updatedAt AS updatedAt",
+    access: Access::Write,
+    params: &["id"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const ADMIN_DELETE_NODE: Query = Query {
+    name: "admin_delete_node",
+    cypher: "
+        MATCH (n:{label})
+        WHERE elementId(n) = $id
+        WITH n, properties(n) AS properties
+        DETACH DELETE n
+        RETURN count(*) AS deleted, head(collect(properties)) AS properties",
+    access: Access::Write,
+    params: &["id"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const MIGRATE_RELEASED: Query = Query {
+    name: "migrate_released",
+    cypher: "
+        MATCH (movie:Movie)
+        WHERE movie.released IS NOT NULL AND NOT toString(movie.released) CONTAINS '-'
+        SET movie.released = date(toString(movie.released))
+        RETURN count(movie) AS migrated",
+    access: Access::Write,
+    params: &[],
+    timeout: Duration::from_secs(60),
+};
+
+pub const CREATE_MIGRATION_CONSTRAINT: Query = Query {
+    name: "create_migration_constraint",
+    cypher: "
+        CREATE CONSTRAINT schema_migration_version IF NOT EXISTS
+        FOR (migration:SchemaMigration) REQUIRE migration.version IS UNIQUE",
+    access: Access::Write,
+    params: &[],
+    timeout: Duration::from_secs(30),
+};
+
+pub const CREATE_MOVIE_ID_CONSTRAINT: Query = Query {
+    name: "create_movie_id_constraint",
+    cypher: "
+        CREATE CONSTRAINT movie_id IF NOT EXISTS
+        FOR (movie:Movie) REQUIRE movie.id IS UNIQUE",
+    access: Access::Write,
+    params: &[],
+    timeout: Duration::from_secs(30),
+};
+
+pub const CREATE_PERSON_ID_CONSTRAINT: Query = Query {
+    name: "create_person_id_constraint",
+    cypher: "
+        CREATE CONSTRAINT person_id IF NOT EXISTS
+        FOR (person:Person) REQUIRE person.id IS UNIQUE",
+    access: Access::Write,
+    params: &[],
+    timeout: Duration::from_secs(30),
+};
+
+pub const CREATE_LOCATION_POINT_INDEX: Query = Query {
+    name: "create_location_point_index",
+    cypher: "
+        CREATE POINT INDEX location_points IF NOT EXISTS
+        FOR (location:Location) ON (location.point)",
+    access: Access::Write,
+    params: &[],
+    timeout: Duration::from_secs(30),
+};
+
+/// Gives movies and people without one a short random id, 48 bits of a
+/// UUID. The constraints reject the unlikely duplicate.
+pub const ASSIGN_IDS: Query = Query {
+    name: "assign_ids",
+    cypher: "
+        MATCH (n)
+        WHERE (n:Movie OR n:Person) AND n.id IS NULL
+        SET n.id = substring(replace(randomUUID(), '-', ''), 0, 12)
+        RETURN count(n) AS assigned",
+    access: Access::Write,
+    params: &[],
+    timeout: Duration::from_secs(60),
+};
+
+pub const MOVIE_ID: Query = Query {
+    name: "movie_id",
+    cypher: "
+        MATCH (movie:Movie)
+        WHERE movie.title = $title OR $title IN coalesce(movie.previousTitles, [])
+        RETURN movie.id AS id, movie.title = $title AS current
+        ORDER BY current DESC, id",
+    access: Access::Read,
+    params: &["title"],
+    timeout: Duration::from_secs(5),
+};
+
+pub const TITLE_TAKEN: Query = Query {
+    name: "title_taken",
+    cypher: "
+        OPTIONAL MATCH (other:Movie {title: $new_title})
+        WHERE $new_title <> $title
+        RETURN count(other) > 0 AS taken",
+    access: Access::Read,
+    params: &["title", "new_title"],
+    timeout: Duration::from_secs(5),
+};
+
+/// Running transactions with a query, except for this one.
+pub const RUNNING_QUERIES: Query = Query {
+    name: "running_queries",
+    cypher: "
+        SHOW TRANSACTIONS
+        YIELD transactionId, currentQuery, username, database, status, startTime, elapsedTime
+        WHERE currentQuery <> '' AND NOT currentQuery CONTAINS 'SHOW TRANSACTIONS'
+        RETURN transactionId AS id, currentQuery AS query, username, database, status,
+            toString(startTime) AS startedAt, elapsedTime.milliseconds AS elapsedMs
+        ORDER BY elapsedMs DESC",
+    access: Access::Read,
+    params: &[],
+    timeout: Duration::from_secs(5),
+};
+
+pub const TERMINATE_QUERY: Query = Query {
+    name: "terminate_query",
+    cypher: "
+        TERMINATE TRANSACTIONS $id
+        YIELD transactionId, message
+        RETURN transactionId AS id, message",
+    access: Access::Write,
+    params: &["id"],
+    timeout: Duration::from_secs(5),
+};
+
+/// Node and relationship counts, answered from the count store.
+pub const GRAPH_SIZE: Query = Query {
+    name: "graph_size",
+    cypher: "
+        CALL { MATCH (n) RETURN count(n) AS nodes }
+        CALL { MATCH ()-[r]->() RETURN count(r) AS relationships }
+        RETURN nodes, relationships",
+    access: Access::Read,
+    params: &[],
+    timeout: Duration::from_secs(5),
+};
+
+/// The version of the server, like `4.4.26` or `5.13.0`.
+pub const SERVER_VERSION: Query = Query {
+    name: "server_version",
+    cypher: "
+        CALL dbms.components() YIELD name, versions
+        WHERE name = 'Neo4j Kernel'
+        RETURN versions[0] AS version",
+    access: Access::Read,
+    params: &[],
+    timeout: Duration::from_secs(10),
+};
+
+pub const APPLIED_MIGRATIONS: Query = Query {
+    name: "applied_migrations",
+    cypher: "
+        MATCH (migration:SchemaMigration)
+        RETURN migration.version AS version",
+    access: Access::Read,
+    params: &[],
+    timeout: Duration::from_secs(10),
+};
+
+pub const RECORD_MIGRATION: Query = Query {
+    name: "record_migration",
+    cypher: "
+        MERGE (migration:SchemaMigration {version: $version})
+        ON CREATE SET migration.description = $description, migration.appliedAt = datetime()
+        RETURN migration.version AS version",
+    access: Access::Write,
+    params: &["version", "description"],
+    timeout: Duration::from_secs(10),
+};
+
+pub const ALL: &[&Query] = &[
+    &FIND_MOVIE,
+    &FIND_MOVIE_BY_ID,
+    &VOTE_IN_MOVIE,
+    &VOTE_IN_MOVIES,
+    &LOCK_MOVIE,
+    &UPDATE_MOVIE,
+    &SEARCH_MOVIES,
+    &GRAPH,
+    &RELEASED_BETWEEN,
+    &MOVIES_NEAR,
+    &ADD_FILMING_LOCATION,
+    &CREATE_VECTOR_INDEX,
+    &MOVIES_WITHOUT_EMBEDDING,
+    &SET_EMBEDDINGS,
+    &SEMANTIC_SEARCH,
+    &CREATE_FULLTEXT_INDEX,
+    &FULLTEXT_SEARCH,
+    &COUNT_MOVIES,
+    &SITEMAP_MOVIES,
+    &NEWEST_MOVIES,
+    &EXPORT_MOVIES,
+    &EXPORT_PEOPLE,
+    &EXPORT_RELATIONSHIPS,
+    &TOP_VOTED_MOVIES,
+    &LABELS,
+    &ADMIN_LIST_NODES,
+    &ADMIN_GET_NODE,
+    &ADMIN_CREATE_NODE,
+    &ADMIN_UPDATE_NODE,
+    &ADMIN_LOCK_NODE,
+    &ADMIN_DELETE_NODE,
+    &MIGRATE_RELEASED,
+    &CREATE_MIGRATION_CONSTRAINT,
+    &CREATE_MOVIE_ID_CONSTRAINT,
+    &CREATE_PERSON_ID_CONSTRAINT,
+    &CREATE_LOCATION_POINT_INDEX,
+    &ASSIGN_IDS,
+    &MOVIE_ID,
+    &TITLE_TAKEN,
+    &RUNNING_QUERIES,
+    &GRAPH_SIZE,
+    &TERMINATE_QUERY,
+    &APPLIED_MIGRATIONS,
+    &SERVER_VERSION,
+    &RECORD_MIGRATION,
+];
+
+pub fn get(name: &str) -> Result<&'static Query> {
+    ALL.iter()
+        .copied()
+        .find(|q| q.name == name)
+        .ok_or_else(|| eyre!("unknown query: {name}"))
+}
+
+/// Checks that query names are unique and that every `$param` used in the
+/// Cypher text is declared (and vice versa).
+pub fn validate() -> Result<()> {
+    let mut names = HashSet::new();
+    for query in ALL {
+        if !names.insert(query.name) {
+            bail!("duplicate query name: {}", query.name);
+        }
+
+        let used = referenced_params(query.cypher);
+        let declared = query.params.iter().copied().collect::<HashSet<_>>();
+        if let Some(param) = used.difference(&declared).next() {
+            bail!("query {} uses undeclared parameter ${param}", query.name);
+        }
+        if let Some(param) = declared.difference(&used).next() {
+            bail!("query {} declares unused parameter {param}", query.name);
+        }
+    }
+    Ok(())
+}
+
+impl Query {
+    /// Builds the driver query, checking the parameters against the declared ones.
+    pub fn prepare(
+        &self,
+        params: impl IntoIterator<Item = (&'static str, BoltType)>,
+    ) -> Result<neo4rs::Query> {
+        self.prepare_labeled(None, params)
+    }
+
+    /// Like [`Query::prepare`], also substituting the [`LABEL`] placeholder.
+    pub fn prepare_labeled(
+        &self,
+        label: Option<&str>,
+        params: impl IntoIterator<Item = (&'static str, BoltType)>,
+    ) -> Result<neo4rs::Query> {
+        let params = params.into_iter().collect::<Vec<_>>();
+        self.check_params(params.iter().map(|(k, _)| *k))?;
+
+        Ok(neo4rs::Query::new(self.labeled_cypher(label)?).params(params))
+    }
+
+    fn labeled_cypher(&self, label: Option<&str>) -> Result<String> {
+        Ok(match (self.cypher.contains(LABEL), label) {
+            (false, None) => self.cypher.to_owned(),
+            (true, Some(label)) => {
+                let label = format!("`{}`", label.replace('`', "``"));
+                self.cypher.replace(LABEL, &label)
+            }
+            (true, None) => bail!("query {} needs a label", self.name),
+            (false, Some(_)) => bail!("query {} does not take a label", self.name),
+        })
+    }
+
+    /// Checks that the given parameter names are exactly the declared ones.
+    fn check_params<'a>(&self, given: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let given = given.into_iter().collect::<HashSet<_>>();
+        for param in self.params {
+            if !given.contains(param) {
+                bail!("query {} is missing parameter {param}", self.name);
+            }
+        }
+        if let Some(param) = given.iter().find(|p| !self.params.contains(p)) {
+            bail!("query {} got unexpected parameter {param}", self.name);
+        }
+        Ok(())
+    }
+}
+
+fn referenced_params(cypher: &str) -> HashSet<&str> {
+    cypher
+        .split('$')
+        .skip(1)
+        .map(|rest| {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            &rest[..end]
+        })
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_is_valid() {
+        validate().unwrap();
+    }
+
+    #[test]
+    fn escapes_backticks_in_labels() {
+        let cypher = ADMIN_GET_NODE
+            .labeled_cypher(Some("Movie`) DETACH DELETE (n"))
+            .unwrap();
+        assert!(cypher.contains("MATCH (n:`Movie``) DETACH DELETE (n`)"));
+
+        assert!(ADMIN_GET_NODE.labeled_cypher(None).is_err());
+        assert!(FIND_MOVIE.labeled_cypher(Some("Movie")).is_err());
+    }
+
+    #[test]
+    fn checks_given_params() {
+        assert!(FIND_MOVIE.prepare([("title", "The Matrix".into())]).is_ok());
+        assert!(FIND_MOVIE.prepare([]).is_err());
+        assert!(FIND_MOVIE
+            .prepare([("title", "The Matrix".into()), ("limit", 1.into())])
+            .is_err());
+    }
+}
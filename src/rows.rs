@@ -0,0 +1,33 @@
+use std::any::type_name;
+
+use color_eyre::eyre::{eyre, Result};
+use futures::{stream::BoxStream, StreamExt as _, TryStreamExt as _};
+use neo4rs::{BoltMap, BoltType, Row};
+use serde::{de::DeserializeOwned, de::IntoDeserializer as _};
+
+pub type Rows = BoxStream<'static, Result<Row, neo4rs::Error>>;
+
+/// Maps a row onto `T` by matching its columns to the fields of `T`.
+///
+/// Errors name the column (and the path inside it) that could not be mapped.
+pub fn from_row<T: DeserializeOwned>(row: &Row) -> Result<T> {
+    let columns = BoltType::Map(row.to_strict::<BoltMap>()?);
+
+    serde_path_to_error::deserialize((&columns).into_deserializer()).map_err(|err| {
+        let path = err.path().to_string();
+        let target = type_name::<T>().rsplit("::").next().unwrap_or_default();
+        if path == "." {
+            eyre!("could not map row to {target}: {}", err.inner())
+        } else {
+            eyre!("could not map column `{path}` to {target}: {}", err.inner())
+        }
+    })
+}
+
+pub fn map<T: DeserializeOwned + Send + 'static>(rows: Rows) -> BoxStream<'static, Result<T>> {
+    rows.map(|row| from_row(&row?)).boxed()
+}
+
+pub async fn collect<T: DeserializeOwned + Send + 'static>(rows: Rows) -> Result<Vec<T>> {
+    map(rows).try_collect().await
+}
@@ -0,0 +1,206 @@
+use color_eyre::eyre::{Report, Result};
+use serde::Deserialize;
+use tracing::{info, instrument};
+
+use crate::{
+    queries::{self, Query},
+    rows, Service,
+};
+
+/// Failure codes of users that may not change the schema, like the
+/// read-only user of the demo database, or of read-only databases.
+const FORBIDDEN_CODES: &[&str] = &[
+    "Neo.ClientError.Security.Forbidden",
+    "Neo.ClientError.General.ForbiddenOnReadOnlyDatabase",
+];
+
+/// A schema change that is applied once and then recorded as a
+/// `SchemaMigration` node.
+struct Step {
+    version: i64,
+    description: &'static str,
+    query: &'static Query,
+    /// The oldest major Neo4j version that supports the step. On older
+    /// servers it is skipped, and applied once the server is upgraded.
+    since: i64,
+}
+
+/// Schema steps in the order they are applied. Steps are never edited once
+/// released, changes to an index get a new step that replaces it.
+const STEPS: &[Step] = &[
+    Step {
+        version: 1,
+        description: "unique schema migration versions",
+        query: &queries::CREATE_MIGRATION_CONSTRAINT,
+        since: 4,
+    },
+    Step {
+        version: 2,
+        description: "full-text index on movie titles and taglines",
+        query: &queries::CREATE_FULLTEXT_INDEX,
+        since: 4,
+    },
+    Step {
+        version: 3,
+        description: "unique movie ids",
+        query: &queries::CREATE_MOVIE_ID_CONSTRAINT,
+        since: 4,
+    },
+    Step {
+        version: 4,
+        description: "unique person ids",
+        query: &queries::CREATE_PERSON_ID_CONSTRAINT,
+        since: 4,
+    },
+    Step {
+        version: 5,
+        description: "point index on filming locations",
+        query: &queries::CREATE_LOCATION_POINT_INDEX,
+        since: 5,
+    },
+];
+
+impl Service {
+    /// Applies the schema steps that have not been recorded yet.
+    ///
+    /// Every step is idempotent, so that instances starting at the same time
+    /// or a crash between applying and recording a step do no harm.
+    #[instrument(skip(self))]
+    pub async fn apply_schema(&self) -> Result<()> {
+        let applied = self
+            .execute_read(|tx| {
+                Box::pin(async move {
+                    let rows = tx.execute(queries::APPLIED_MIGRATIONS.name, []).await?;
+                    rows.iter()
+                        .map(|row| rows::from_row::<Migration>(row).map(|m| m.version))
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .await?;
+        let server = self
+            .execute_read(|tx| {
+                Box::pin(async move {
+                    let rows = tx.execute(queries::SERVER_VERSION.name, []).await?;
+                    Ok(rows::from_row::<Server>(&rows[0])?.version)
+                })
+            })
+            .await?;
+        let major = major_version(&server);
+
+        for step in STEPS.iter().filter(|step| !applied.contains(&step.version)) {
+            if major < step.since {
+                info!(
+                    step.version,
+                    step.description, server, "skipped schema step the server does not support"
+                );
+                continue;
+            }
+            // Neo4j does not allow schema and data changes in one transaction
+            self.execute_write(|tx| Box::pin(async move { tx.execute(step.query.name, []).await }))
+                .await?;
+            self.execute_write(|tx| {
+                Box::pin(async move {
+                    tx.execute(
+                        queries::RECORD_MIGRATION.name,
+                        [
+                            ("version", step.version.into()),
+                            ("description", step.description.into()),
+                        ],
+                    )
+                    .await
+                })
+            })
+            .await?;
+
+            info!(step.version, step.description, "applied schema step");
+        }
+
+        // not a step, since movies and people can be loaded without an id at
+        // any time, e.g. with the movies dataset
+        let assigned = self
+            .execute_write(|tx| {
+                Box::pin(async move {
+                    let rows = tx.execute(queries::ASSIGN_IDS.name, []).await?;
+                    Ok(rows::from_row::<Assigned>(&rows[0])?.assigned)
+                })
+            })
+            .await?;
+        if assigned > 0 {
+            info!(assigned, "assigned ids to movies and people");
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether applying the schema failed because the database does not allow
+/// it, rather than because of the schema itself.
+pub fn is_forbidden(err: &Report) -> bool {
+    match err.downcast_ref::<neo4rs::Error>() {
+        Some(neo4rs::Error::UnexpectedMessage(msg)) => {
+            FORBIDDEN_CODES.iter().any(|code| msg.contains(code))
+        }
+        _ => false,
+    }
+}
+
+/// The major version of a Neo4j version like `4.4.26` or `5.13.0`, 0 if it
+/// cannot be read.
+fn major_version(version: &str) -> i64 {
+    version
+        .split('.')
+        .next()
+        .and_then(|major| major.parse().ok())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Deserialize)]
+struct Server {
+    version: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Migration {
+    version: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Assigned {
+    assigned: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::eyre::eyre;
+
+    use super::*;
+
+    #[test]
+    fn recognizes_users_without_write_access() {
+        let forbidden = neo4rs::Error::UnexpectedMessage(
+            "Failure(Neo.ClientError.Security.Forbidden, Schema operations are not allowed for user 'movies')"
+                .to_owned(),
+        );
+        assert!(is_forbidden(&forbidden.into()));
+
+        let invalid = neo4rs::Error::UnexpectedMessage(
+            "Failure(Neo.ClientError.Schema.EquivalentSchemaRuleAlreadyExists, ...)".to_owned(),
+        );
+        assert!(!is_forbidden(&invalid.into()));
+        assert!(!is_forbidden(&eyre!("Neo.ClientError.Security.Forbidden")));
+    }
+
+    #[test]
+    fn reads_major_versions() {
+        assert_eq!(major_version("4.4.26"), 4);
+        assert_eq!(major_version("5.13.0"), 5);
+        assert_eq!(major_version("dev"), 0);
+    }
+
+    #[test]
+    fn point_index_waits_for_neo4j_5() {
+        let step = STEPS.iter().find(|step| step.version == 5).unwrap();
+        assert_eq!(step.query.name, queries::CREATE_LOCATION_POINT_INDEX.name);
+        assert_eq!(step.since, 5);
+    }
+}
@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use color_eyre::eyre::Result;
+use neo4rs::{BoltMap, BoltType};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    lenient,
+    limits::{self, Limited},
+    queries, rows, AppError, HttpError, Movie, Service,
+};
+
+const BATCH_SIZE: i64 = 50;
+
+/// How often movies without an embedding are looked for. Edits to a title,
+/// tagline or plot drop the embedding, so it is recomputed by the next run.
+const INDEX_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+pub fn routes() -> Router<Service> {
+    Router::new().route("/search/semantic", get(semantic_search))
+}
+
+async fn semantic_search(
+    Query(search): Query<SemanticSearch>,
+    Limited(limit, _): Limited<limits::Semantic>,
+    State(service): State<Service>,
+) -> Result<Json<Vec<ScoredMovie>>, AppError> {
+    Ok(Json(service.semantic_search(search, limit).await?))
+}
+
+impl Service {
+    /// Creates the vector index and embeds every movie that has a tagline
+    /// or plot but no embedding yet.
+    #[instrument(skip(self))]
+    pub async fn index_embeddings(&self) -> Result<()> {
+        let Some(embedder) = &self.embedder else {
+            return Ok(());
+        };
+
+        let dimensions = i64::try_from(embedder.dimensions())?;
+        self.execute_write(|tx| {
+            Box::pin(async move {
+                tx.execute(
+                    queries::CREATE_VECTOR_INDEX.name,
+                    [("dimensions", dimensions.into())],
+                )
+                .await
+            })
+        })
+        .await?;
+
+        let mut embedded = 0;
+        loop {
+            let rows = self
+                .execute_read(|tx| {
+                    Box::pin(async move {
+                        tx.execute(
+                            queries::MOVIES_WITHOUT_EMBEDDING.name,
+                            [("limit", BATCH_SIZE.into())],
+                        )
+                        .await
+                    })
+                })
+                .await?;
+            let pending = rows
+                .iter()
+                .map(rows::from_row::<PendingEmbedding>)
+                .collect::<Result<Vec<_>>>()?;
+            if pending.is_empty() {
+                break;
+            }
+
+            let texts = pending.iter().map(|p| p.text.clone()).collect::<Vec<_>>();
+            let vectors = embedder.embed(&texts).await?;
+
+            let embeddings = pending
+                .iter()
+                .zip(vectors)
+                .map(|(pending, vector)| {
+                    let mut row = BoltMap::default();
+                    row.put("id".into(), pending.id.clone().into());
+                    row.put("embedding".into(), vector.into());
+                    BoltType::Map(row)
+                })
+                .collect::<Vec<_>>();
+
+            self.execute_write(|tx| {
+                let embeddings = embeddings.clone();
+                Box::pin(async move {
+                    tx.execute(
+                        queries::SET_EMBEDDINGS.name,
+                        [("embeddings", embeddings.into())],
+                    )
+                    .await
+                })
+            })
+            .await?;
+
+            embedded += pending.len();
+            debug!(embedded, "stored embeddings");
+        }
+
+        if embedded > 0 {
+            info!(embedded, "movie embeddings are up to date");
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn semantic_search(
+        &self,
+        search: SemanticSearch,
+        limit: i64,
+    ) -> Result<Vec<ScoredMovie>> {
+        let embedding = self.embed_query(&search.q).await?;
+
+        let rows = self
+            .execute(
+                queries::SEMANTIC_SEARCH.name,
+                [("limit", limit.into()), ("embedding", embedding.into())],
+            )
+            .await?;
+
+        let movies = rows::collect::<ScoredMovie>(rows).await?;
+
+        debug!(?movies);
+
+        Ok(movies)
+    }
+
+    /// Embeds a search query, failing with 503 when no provider is configured.
+    pub async fn embed_query(&self, q: &str) -> Result<Vec<f32>> {
+        let Some(embedder) = &self.embedder else {
+            return Err(HttpError::service_unavailable("semantic search is not configured").into());
+        };
+
+        Ok(embedder
+            .embed(&[q.to_owned()])
+            .await?
+            .pop()
+            .unwrap_or_default())
+    }
+}
+
+/// Embeds movies in the background, right away and then periodically, so
+/// that startup does not wait for the embedding provider and new or edited
+/// movies are embedded too.
+pub fn spawn_indexer(service: &Service) {
+    if service.embedder.is_none() {
+        return;
+    }
+
+    let service = service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(INDEX_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(err) = service.index_embeddings().await {
+                warn!("could not index movie embeddings: {err:?}");
+            }
+        }
+    });
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SemanticSearch {
+    q: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingEmbedding {
+    /// The element id, since titles need not be unique.
+    id: String,
+    text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredMovie {
+    #[serde(skip_serializing)]
+    pub id: String,
+    pub movie: Movie,
+    #[serde(default, deserialize_with = "lenient::null_as_default")]
+    pub score: f64,
+}
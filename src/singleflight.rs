@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use color_eyre::eyre::{eyre, Report, Result};
+use futures::future::{BoxFuture, FutureExt as _, Shared};
+
+use crate::{
+    tx::{self, Unreachable},
+    HttpError,
+};
+
+type Flight<V> = Shared<BoxFuture<'static, Result<V, Arc<Report>>>>;
+
+/// Deduplicates concurrent loads of the same key: while a load is in flight,
+/// callers asking for the same key wait for its result instead of starting
+/// another one. Clones share the loads in flight.
+pub struct Flights<K, V> {
+    inflight: Arc<Mutex<HashMap<K, Flight<V>>>>,
+}
+
+impl<K, V> Flights<K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            inflight: Arc::default(),
+        }
+    }
+
+    /// Runs `load` for `key`, or joins the load that is already running for
+    /// it. Whoever waits drives the load, so it completes even if the caller
+    /// that started it goes away.
+    pub async fn run<F>(&self, key: K, load: F) -> Result<V>
+    where
+        F: Future<Output = Result<V>> + Send + 'static,
+    {
+        let flight = {
+            let mut inflight = self.inflight.lock().unwrap_or_else(|e| e.into_inner());
+            match inflight.get(&key) {
+                Some(flight) => flight.clone(),
+                None => {
+                    let flights = self.inflight.clone();
+                    let loaded = key.clone();
+                    let flight = async move {
+                        let result = load.await.map_err(Arc::new);
+                        flights
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .remove(&loaded);
+                        result
+                    }
+                    .boxed()
+                    .shared();
+                    inflight.insert(key, flight.clone());
+                    flight
+                }
+            }
+        };
+
+        flight.await.map_err(|err| replicate(&err))
+    }
+}
+
+/// The error is shared by every caller, so each gets its own report. Errors
+/// with a status keep it, as do connection failures, the others only keep
+/// their message.
+fn replicate(err: &Report) -> Report {
+    match err.downcast_ref::<HttpError>() {
+        Some(err) => err.clone().into(),
+        None if tx::is_connection_failure(err) => Unreachable(format!("{err:#}")).into(),
+        None => eyre!("{err:#}"),
+    }
+}
+
+impl<K, V> Clone for Flights<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inflight: self.inflight.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use axum::http::StatusCode;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_calls_share_one_load() {
+        let flights = Flights::new();
+        let loads = Arc::new(AtomicUsize::new(0));
+
+        let calls = (0..10).map(|_| {
+            let loads = loads.clone();
+            flights.run("matrix", async move {
+                loads.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(1999)
+            })
+        });
+        let results = futures::future::join_all(calls).await;
+
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(results.into_iter().all(|result| result.unwrap() == 1999));
+    }
+
+    #[tokio::test]
+    async fn later_calls_load_again() {
+        let flights = Flights::new();
+        assert!(flights
+            .run("matrix", async { Err(eyre!("down")) })
+            .await
+            .is_err());
+        assert_eq!(
+            flights.run("matrix", async { Ok(1999) }).await.unwrap(),
+            1999
+        );
+    }
+
+    #[tokio::test]
+    async fn every_caller_gets_the_status_of_an_error() {
+        let flights = Flights::<_, i64>::new();
+        let calls = (0..3).map(|_| {
+            flights.run("matrix", async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Err(HttpError::service_unavailable("the database cannot be reached").into())
+            })
+        });
+
+        for result in futures::future::join_all(calls).await {
+            let err = result.unwrap_err();
+            let err = err.downcast_ref::<HttpError>().unwrap();
+            assert_eq!(err.status, StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(err.message, "the database cannot be reached");
+        }
+    }
+}
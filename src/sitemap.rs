@@ -0,0 +1,256 @@
+use std::{future::ready, time::Duration};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE, HOST},
+        HeaderMap,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use color_eyre::eyre::Result;
+use futures::{stream, StreamExt as _, TryStreamExt as _};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::{instrument, warn};
+
+use crate::{
+    cache::Cache, ids::movie_path, queries, rows, rows::Rows, xml::escape, AppError, HttpError,
+    Service,
+};
+
+/// The most URLs a single sitemap file may list.
+const URLS_PER_SITEMAP: i64 = 50_000;
+
+pub const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+const URLSET_START: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+    <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n";
+const URLSET_END: &str = "</urlset>\n";
+
+/// Rendered sitemaps, by file. They are only cached with `PUBLIC_URL`, so
+/// that the `Host` headers of requests cannot fill the cache.
+pub type Sitemaps = Cache<Sitemap, Bytes>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sitemap {
+    /// `/sitemap.xml`, either the only sitemap or an index of all of them.
+    Root,
+    /// `/sitemaps/<n>.xml`, counting from 1.
+    Page(i64),
+}
+
+pub fn routes() -> Router<Service> {
+    Router::new()
+        .route("/sitemap.xml", get(root))
+        .route("/sitemaps/:file", get(page))
+}
+
+async fn root(State(service): State<Service>, headers: HeaderMap) -> Result<Response, AppError> {
+    let base = base_url(&headers)?;
+    Ok(xml(service.sitemap(base, Sitemap::Root).await?))
+}
+
+async fn page(
+    Path(file): Path<String>,
+    State(service): State<Service>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let page = file
+        .strip_suffix(".xml")
+        .and_then(|page| page.parse::<i64>().ok())
+        .filter(|page| *page >= 1)
+        .ok_or_else(|| HttpError::not_found(format!("no sitemap {file}")))?;
+
+    let base = base_url(&headers)?;
+    Ok(xml(service.sitemap(base, Sitemap::Page(page)).await?))
+}
+
+fn xml(body: Body) -> Response {
+    (
+        [
+            (CONTENT_TYPE, "application/xml"),
+            (CACHE_CONTROL, "public, max-age=3600"),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+impl Service {
+    /// Serves a sitemap from the cache, or streams it from the graph while
+    /// caching it for the next request.
+    #[instrument(skip(self))]
+    async fn sitemap(&self, base: String, sitemap: Sitemap) -> Result<Body> {
+        let cache = public_url().map(|_| self.sitemaps.clone());
+        if let Some(xml) = cache.as_ref().and_then(|cache| cache.get(&sitemap)) {
+            return Ok(Body::from(xml));
+        }
+
+        let rows = self.execute(queries::COUNT_MOVIES.name, []).await?;
+        let Count { movies } = rows::collect::<Count>(rows)
+            .await?
+            .pop()
+            .unwrap_or(Count { movies: 0 });
+        let pages = ((movies + URLS_PER_SITEMAP - 1) / URLS_PER_SITEMAP).max(1);
+
+        let page = match sitemap {
+            Sitemap::Root if pages > 1 => {
+                let xml = Bytes::from(sitemap_index(&base, pages));
+                if let Some(cache) = cache {
+                    cache.insert(sitemap, xml.clone());
+                }
+                return Ok(Body::from(xml));
+            }
+            Sitemap::Root => 1,
+            Sitemap::Page(page) if page <= pages => page,
+            Sitemap::Page(page) => {
+                return Err(HttpError::not_found(format!("no sitemap {page}.xml")).into())
+            }
+        };
+
+        let rows = self
+            .execute(
+                queries::SITEMAP_MOVIES.name,
+                [
+                    ("skip", ((page - 1) * URLS_PER_SITEMAP).into()),
+                    ("limit", URLS_PER_SITEMAP.into()),
+                ],
+            )
+            .await?;
+
+        Ok(stream_urlset(cache, sitemap, base, rows))
+    }
+}
+
+/// Streams the `urlset` for the movie rows as they arrive, and caches it
+/// once it was sent completely.
+fn stream_urlset(cache: Option<Sitemaps>, sitemap: Sitemap, base: String, rows: Rows) -> Body {
+    let (sender, receiver) = mpsc::channel::<Result<Bytes, std::io::Error>>(16);
+
+    tokio::spawn(async move {
+        let mut chunks = stream::once(ready(Ok(URLSET_START.to_owned())))
+            .chain(rows::map::<SitemapMovie>(rows).map_ok(move |movie| movie.to_url(&base)))
+            .chain(stream::once(ready(Ok(URLSET_END.to_owned()))));
+
+        let mut xml = Vec::new();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => Bytes::from(chunk),
+                Err(err) => {
+                    warn!("could not stream sitemap: {err:?}");
+                    let _ = sender
+                        .send(Err(std::io::Error::other(err.to_string())))
+                        .await;
+                    return;
+                }
+            };
+            xml.extend_from_slice(&chunk);
+            if sender.send(Ok(chunk)).await.is_err() {
+                // the client went away, the sitemap is incomplete
+                return;
+            }
+        }
+        if let Some(cache) = cache {
+            cache.insert(sitemap, Bytes::from(xml));
+        }
+    });
+
+    Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    }))
+}
+
+fn sitemap_index(base: &str, pages: i64) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for page in 1..=pages {
+        xml.push_str(&format!(
+            "<sitemap><loc>{}</loc></sitemap>\n",
+            escape(&format!("{base}/sitemaps/{page}.xml"))
+        ));
+    }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+/// The public URL of the application, from `PUBLIC_URL` or else the `Host`
+/// the request was sent to.
+pub fn base_url(headers: &HeaderMap) -> Result<String> {
+    if let Some(url) = public_url() {
+        return Ok(url);
+    }
+
+    let host = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .ok_or_else(|| HttpError::bad_request("set PUBLIC_URL or send a Host header"))?;
+    Ok(format!("http://{host}"))
+}
+
+fn public_url() -> Option<String> {
+    std::env::var("PUBLIC_URL")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(|url| url.trim_end_matches('/').to_owned())
+}
+
+/// The public URL of a movie.
+pub fn movie_url(base: &str, id: &str) -> String {
+    format!("{base}{}", movie_path(id))
+}
+
+#[derive(Debug, Deserialize)]
+struct Count {
+    movies: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SitemapMovie {
+    id: String,
+    lastmod: Option<String>,
+}
+
+impl SitemapMovie {
+    fn to_url(&self, base: &str) -> String {
+        let loc = escape(&movie_url(base, &self.id));
+        match &self.lastmod {
+            Some(lastmod) => format!(
+                "<url><loc>{loc}</loc><lastmod>{}</lastmod></url>\n",
+                escape(lastmod)
+            ),
+            None => format!("<url><loc>{loc}</loc></url>\n"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_ids_into_urls() {
+        let movie = SitemapMovie {
+            id: "a/b & c".to_owned(),
+            lastmod: Some("2024-01-01".to_owned()),
+        };
+        assert_eq!(
+            movie.to_url("https://movies.example.com"),
+            "<url><loc>https://movies.example.com/movie/id/a%2Fb%20%26%20c</loc>\
+             <lastmod>2024-01-01</lastmod></url>\n"
+        );
+    }
+
+    #[test]
+    fn indexes_all_pages() {
+        let index = sitemap_index("http://localhost:8080", 2);
+        assert!(index.contains("<loc>http://localhost:8080/sitemaps/1.xml</loc>"));
+        assert!(index.contains("<loc>http://localhost:8080/sitemaps/2.xml</loc>"));
+        assert!(!index.contains("3.xml"));
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+use chrono::{Datelike as _, NaiveDate};
+use serde::{de, Deserializer, Serializer};
+
+/// Serde support for the `released` property of a movie.
+///
+/// The property is stored as a Neo4j date, but older data (and the public
+/// movies dataset) store a bare year. Both are accepted when reading; the
+/// API keeps reporting the year so existing clients are unaffected.
+pub mod release_year {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(date: &Option<NaiveDate>, s: S) -> Result<S::Ok, S::Error> {
+        match date {
+            Some(date) => s.serialize_some(&date.year()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<NaiveDate>, D::Error> {
+        d.deserialize_option(ReleasedVisitor)
+    }
+}
+
+struct ReleasedVisitor;
+
+impl<'de> de::Visitor<'de> for ReleasedVisitor {
+    type Value = Option<NaiveDate>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a date or a year")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+        d.deserialize_any(self)
+    }
+
+    fn visit_i64<E: de::Error>(self, year: i64) -> Result<Self::Value, E> {
+        i32::try_from(year)
+            .ok()
+            .and_then(|year| NaiveDate::from_ymd_opt(year, 1, 1))
+            .map(Some)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Signed(year), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, year: u64) -> Result<Self::Value, E> {
+        let year = i64::try_from(year)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(year), &self))?;
+        self.visit_i64(year)
+    }
+
+    fn visit_str<E: de::Error>(self, date: &str) -> Result<Self::Value, E> {
+        date.parse::<NaiveDate>()
+            .map(Some)
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(date), &self))
+    }
+}
@@ -0,0 +1,81 @@
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get},
+    Json, Router,
+};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use crate::{queries, rows, AppError, HttpError, Service};
+
+/// Lists the queries running on the database and terminates them, e.g. a
+/// runaway `/graph` request during a demo.
+pub fn routes() -> Router<Service> {
+    Router::new()
+        .route("/admin/queries", get(list))
+        .route("/admin/queries/:id", delete(terminate))
+}
+
+async fn list(State(service): State<Service>) -> Result<Json<Vec<RunningQuery>>, AppError> {
+    Ok(Json(service.running_queries().await?))
+}
+
+async fn terminate(
+    Path(id): Path<String>,
+    State(service): State<Service>,
+) -> Result<Json<Terminated>, AppError> {
+    Ok(Json(service.terminate_query(id).await?))
+}
+
+impl Service {
+    #[instrument(skip(self))]
+    async fn running_queries(&self) -> Result<Vec<RunningQuery>> {
+        let rows = self.execute(queries::RUNNING_QUERIES.name, []).await?;
+        rows::collect::<RunningQuery>(rows).await
+    }
+
+    #[instrument(skip(self))]
+    async fn terminate_query(&self, id: String) -> Result<Terminated> {
+        let rows = self
+            .execute(queries::TERMINATE_QUERY.name, [("id", id.clone().into())])
+            .await?;
+        let terminated = rows::collect::<Terminated>(rows)
+            .await?
+            .pop()
+            .ok_or_else(|| HttpError::not_found(format!("no transaction {id}")))?;
+
+        if !terminated.is_terminated() {
+            return Err(HttpError::not_found(format!("{id}: {}", terminated.message)).into());
+        }
+        info!(id, "terminated transaction");
+        Ok(terminated)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RunningQuery {
+    id: String,
+    query: String,
+    username: Option<String>,
+    database: Option<String>,
+    status: Option<String>,
+    #[serde(rename = "startedAt")]
+    started_at: Option<String>,
+    #[serde(rename = "elapsedMs")]
+    elapsed_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Terminated {
+    id: String,
+    message: String,
+}
+
+impl Terminated {
+    /// `TERMINATE TRANSACTIONS` answers unknown or finished transactions
+    /// with a message instead of an error.
+    fn is_terminated(&self) -> bool {
+        self.message == "Transaction terminated."
+    }
+}
@@ -0,0 +1,303 @@
+use std::{future::Future, time::Duration};
+
+use color_eyre::eyre::{bail, Report, Result};
+use futures::future::BoxFuture;
+use neo4rs::{BoltType, Graph, Row, Txn};
+use tracing::{debug, warn};
+
+use crate::queries::{self, Access};
+
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+/// The driver has no connect timeout, so without one a request to an
+/// unreachable server waits for the operating system to give up.
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Failure codes after which the whole transaction can safely be run again.
+const RETRYABLE_CODES: &[&str] = &[
+    "Neo.TransientError.",
+    "Neo.ClientError.Cluster.NotALeader",
+    "Neo.ClientError.General.ForbiddenOnReadOnlyDatabase",
+];
+
+/// Transient codes that are caused by the client and must not be retried.
+const NON_RETRYABLE_CODES: &[&str] = &[
+    "Neo.TransientError.Transaction.Terminated",
+    "Neo.TransientError.Transaction.LockClientStopped",
+];
+
+/// An explicit transaction that runs registry queries.
+pub struct Tx {
+    txn: Txn,
+    access: Access,
+}
+
+impl Tx {
+    /// Runs the named query and collects all of its rows.
+    pub async fn execute(
+        &mut self,
+        name: &str,
+        params: impl IntoIterator<Item = (&'static str, BoltType)>,
+    ) -> Result<Vec<Row>> {
+        self.execute_labeled(name, None, params).await
+    }
+
+    /// Like [`Tx::execute`], for queries that operate on a label chosen at runtime.
+    pub async fn execute_labeled(
+        &mut self,
+        name: &str,
+        label: Option<&str>,
+        params: impl IntoIterator<Item = (&'static str, BoltType)>,
+    ) -> Result<Vec<Row>> {
+        let query = queries::get(name)?;
+        if self.access == Access::Read && query.access == Access::Write {
+            bail!("query {name} writes to the graph but the transaction is read-only");
+        }
+
+        let q = query.prepare_labeled(label, params)?;
+        self.collect(name, q, query.timeout).await
+    }
+
+    /// Runs Cypher that is not part of the registry, such as generated
+    /// queries. Callers are responsible for vetting the query.
+    pub async fn execute_unregistered(
+        &mut self,
+        q: neo4rs::Query,
+        timeout: Duration,
+    ) -> Result<Vec<Row>> {
+        self.collect("unregistered", q, timeout).await
+    }
+
+    async fn collect(
+        &mut self,
+        name: &str,
+        q: neo4rs::Query,
+        timeout: Duration,
+    ) -> Result<Vec<Row>> {
+        let txn = &mut self.txn;
+        let run = async move {
+            let mut stream = txn.execute(q).await?;
+            let mut rows = Vec::new();
+            while let Some(row) = stream.next(txn.handle()).await? {
+                rows.push(row);
+            }
+            Ok::<_, neo4rs::Error>(rows)
+        };
+
+        let rows = tokio::time::timeout(timeout, run)
+            .await
+            .map_err(|_| Unreachable(format!("query {name} timed out after {timeout:?}")))??;
+
+        Ok(rows)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Outcome {
+    Commit,
+    Rollback,
+}
+
+/// Runs `work` inside a transaction, committing on success and rolling back
+/// on failure. The whole unit of work is retried on transient errors.
+pub async fn run<T, F>(db: &Graph, access: Access, work: F) -> Result<T>
+where
+    F: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<T>>,
+{
+    run_with_retries(|| run_once(db, access, Outcome::Commit, &work)).await
+}
+
+/// Runs `work` inside a transaction that is always rolled back, so that
+/// nothing it does is persisted.
+pub async fn run_rolled_back<T, F>(db: &Graph, access: Access, work: F) -> Result<T>
+where
+    F: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<T>>,
+{
+    run_with_retries(|| run_once(db, access, Outcome::Rollback, &work)).await
+}
+
+/// Runs `work` inside a transaction that is always rolled back, running
+/// `measure` right before and after it in the same transaction. Returns
+/// both measurements and what `work` returned, to preview a mutation.
+pub async fn run_measured<T, S, F, M>(
+    db: &Graph,
+    access: Access,
+    measure: M,
+    work: F,
+) -> Result<(S, T, S)>
+where
+    F: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<T>>,
+    M: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<S>>,
+{
+    run_with_retries(|| measure_once(db, access, &measure, &work)).await
+}
+
+async fn run_with_retries<T, A, Fut>(attempt_once: A) -> Result<T>
+where
+    A: Fn() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match attempt_once().await {
+            Err(err) if attempt < MAX_ATTEMPTS && is_retryable(&err) => {
+                warn!(attempt, ?backoff, "retrying transaction: {err}");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn run_once<T, F>(db: &Graph, access: Access, outcome: Outcome, work: &F) -> Result<T>
+where
+    F: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<T>>,
+{
+    let mut tx = start(db, access).await?;
+
+    let result = work(&mut tx).await;
+    finish(tx, outcome, result).await
+}
+
+async fn measure_once<T, S, F, M>(
+    db: &Graph,
+    access: Access,
+    measure: &M,
+    work: &F,
+) -> Result<(S, T, S)>
+where
+    F: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<T>>,
+    M: for<'t> Fn(&'t mut Tx) -> BoxFuture<'t, Result<S>>,
+{
+    let mut tx = start(db, access).await?;
+
+    let result = async {
+        let before = measure(&mut tx).await?;
+        let value = work(&mut tx).await?;
+        let after = measure(&mut tx).await?;
+        Ok((before, value, after))
+    }
+    .await;
+    finish(tx, Outcome::Rollback, result).await
+}
+
+async fn start(db: &Graph, access: Access) -> Result<Tx> {
+    let txn = tokio::time::timeout(START_TIMEOUT, db.start_txn())
+        .await
+        .map_err(|_| {
+            Unreachable(format!(
+                "starting a transaction timed out after {START_TIMEOUT:?}"
+            ))
+        })??;
+    Ok(Tx { txn, access })
+}
+
+/// The server did not answer in time, or is not tried while it cannot be
+/// reached. A blackholed server does not refuse connections, it never
+/// answers, so timeouts count as connection failures. Not retried.
+#[derive(Debug)]
+pub struct Unreachable(pub String);
+
+impl std::fmt::Display for Unreachable {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Unreachable {}
+
+/// Whether `err` means that the server could not be reached, as opposed to
+/// an error the server answered with.
+pub fn is_connection_failure(err: &Report) -> bool {
+    err.is::<Unreachable>()
+        || matches!(
+            err.downcast_ref::<neo4rs::Error>(),
+            Some(neo4rs::Error::ConnectionError | neo4rs::Error::IOError { .. })
+        )
+}
+
+/// Ends the transaction according to `outcome` if `result` is a success,
+/// and rolls it back otherwise.
+async fn finish<T>(tx: Tx, outcome: Outcome, result: Result<T>) -> Result<T> {
+    match result {
+        Ok(value) => {
+            match outcome {
+                Outcome::Commit => tx.txn.commit().await?,
+                Outcome::Rollback => tx.txn.rollback().await?,
+            }
+            Ok(value)
+        }
+        Err(err) => {
+            if let Err(rollback) = tx.txn.rollback().await {
+                debug!("rollback failed: {rollback}");
+            }
+            Err(err)
+        }
+    }
+}
+
+fn is_retryable(err: &Report) -> bool {
+    match err.downcast_ref::<neo4rs::Error>() {
+        Some(neo4rs::Error::ConnectionError | neo4rs::Error::IOError { .. }) => true,
+        Some(neo4rs::Error::UnexpectedMessage(msg)) => {
+            RETRYABLE_CODES.iter().any(|code| msg.contains(code))
+                && !NON_RETRYABLE_CODES.iter().any(|code| msg.contains(code))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(code: &str) -> Report {
+        neo4rs::Error::UnexpectedMessage(format!("Failure({code}, some message)")).into()
+    }
+
+    fn unreachable() -> Report {
+        Unreachable("query graph timed out after 10s".to_owned()).into()
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        assert!(is_retryable(&failure(
+            "Neo.TransientError.Transaction.DeadlockDetected"
+        )));
+        assert!(is_retryable(&failure("Neo.ClientError.Cluster.NotALeader")));
+        assert!(is_retryable(&neo4rs::Error::ConnectionError.into()));
+    }
+
+    #[test]
+    fn does_not_retry_terminated_transactions() {
+        assert!(!is_retryable(&failure(
+            "Neo.TransientError.Transaction.Terminated"
+        )));
+        assert!(!is_retryable(&failure(
+            "Neo.TransientError.Transaction.LockClientStopped"
+        )));
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        assert!(!is_retryable(&failure(
+            "Neo.ClientError.Statement.SyntaxError"
+        )));
+        assert!(!is_retryable(&Report::msg("invalid input")));
+        assert!(!is_retryable(&unreachable()));
+    }
+
+    #[test]
+    fn recognizes_connection_failures() {
+        assert!(is_connection_failure(&unreachable()));
+        assert!(is_connection_failure(
+            &neo4rs::Error::ConnectionError.into()
+        ));
+        assert!(!is_connection_failure(&failure(
+            "Neo.TransientError.Transaction.DeadlockDetected"
+        )));
+    }
+}
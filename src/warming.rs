@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use tracing::{info, instrument, warn};
+
+use crate::{ids::MovieKey, queries, rows, Service};
+
+/// How long cached movies and graphs are served before they are read again.
+pub const CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Warming runs a bit more often than entries expire, so hot entries are
+/// replaced before they run out.
+const WARM_INTERVAL: Duration = Duration::from_secs(4 * 60);
+
+/// How many of the top-voted and of the most-viewed movies are warmed.
+const HOT_MOVIES: usize = 10;
+
+/// Counts how often each movie was looked up since the server started.
+#[derive(Debug, Clone, Default)]
+pub struct Views(Arc<Mutex<HashMap<MovieKey, u64>>>);
+
+impl Views {
+    pub fn record(&self, movie: MovieKey) {
+        let mut views = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        *views.entry(movie).or_default() += 1;
+    }
+
+    /// The movies with the most views, most viewed first.
+    pub fn top(&self, n: usize) -> Vec<MovieKey> {
+        let views = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let mut movies = views.iter().collect::<Vec<_>>();
+        movies.sort_by(|(a, a_views), (b, b_views)| b_views.cmp(a_views).then(a.cmp(b)));
+        movies
+            .into_iter()
+            .take(n)
+            .map(|(movie, _)| movie.clone())
+            .collect()
+    }
+}
+
+/// Warms the cache right away and then periodically, so that a fresh
+/// deployment does not send every request for popular content to the
+/// database.
+pub fn spawn(service: &Service) {
+    let service = service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(WARM_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(err) = service.warm_cache().await {
+                warn!("could not warm the cache: {err:?}");
+            }
+        }
+    });
+}
+
+impl Service {
+    /// Reads the top-voted and most-viewed movies and the default graph
+    /// into the cache.
+    #[instrument(skip(self))]
+    async fn warm_cache(&self) -> Result<()> {
+        let rows = self
+            .execute(
+                queries::TOP_VOTED_MOVIES.name,
+                [("limit", (HOT_MOVIES as i64).into())],
+            )
+            .await?;
+        let mut keys = rows::collect::<HotMovie>(rows)
+            .await?
+            .into_iter()
+            .map(|movie| match movie.id {
+                Some(id) => MovieKey::Id(id),
+                None => MovieKey::Title(movie.title),
+            })
+            .collect::<Vec<_>>();
+        for key in self.views.top(HOT_MOVIES) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        // one movie that cannot be read does not keep the others cold
+        let mut warmed = 0;
+        for key in keys {
+            match self.load_movie(key.clone()).await {
+                Ok(movie) if movie.title.is_some() => {
+                    self.movies.insert(key, movie);
+                    warmed += 1;
+                }
+                Ok(_) => {}
+                Err(err) => warn!(?key, "could not warm a movie: {err:?}"),
+            }
+        }
+
+        let limit = self.limits.graph.default;
+        let graph = self.load_graph(limit).await?;
+        self.graphs.insert(limit, graph);
+
+        info!(movies = warmed, "warmed the cache");
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HotMovie {
+    id: Option<String>,
+    title: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_movies_by_views() {
+        let views = Views::default();
+        for title in ["Top Gun", "The Matrix", "The Matrix", "Cast Away"] {
+            views.record(MovieKey::Title(title.to_owned()));
+        }
+        assert_eq!(
+            views.top(2),
+            [
+                MovieKey::Title("The Matrix".to_owned()),
+                MovieKey::Title("Cast Away".to_owned())
+            ]
+        );
+    }
+}